@@ -14,13 +14,13 @@ fn main() -> Result<()> {
     // ----- UI初期化 -----
     let mut ui = ClientUI::new()?;
     let mut state = AppState::new()?;
-    let input_handler = InputHandler::new();
+    let mut input_handler = InputHandler::new();
 
     // ----- メインループ -----
     loop {
         // ----- 非ブロッキングで受信メッセージ取得 -----
-        for msg in rx.try_iter() {
-            state.add_message(msg);
+        for packet in rx.try_iter() {
+            state.handle_packet(packet);
         }
 
         // ----- キー入力処理 -----
@@ -31,18 +31,81 @@ fn main() -> Result<()> {
                 network_manager.send_nickname(nickname)?;
             },
             KeyAction::SendMessage(message) => {
+                match (&state.nickname, &state.current_room) {
+                    (Some(nickname), Some(room)) => {
+                        network_manager.send_message(message, nickname.clone(), room.clone())?;
+                    },
+                    _ => state.add_message("You are not in any room. Use /join <room>".to_string()),
+                }
+            },
+            KeyAction::SendMessageWithKind(kind, message) => {
+                match (&state.nickname, &state.current_room) {
+                    (Some(nickname), Some(room)) => {
+                        network_manager.send_message_with_kind(message, nickname.clone(), room.clone(), kind)?;
+                    },
+                    _ => state.add_message("You are not in any room. Use /join <room>".to_string()),
+                }
+            },
+            KeyAction::SendReply(reply_to, message) => {
+                // 返信は返信先のメッセージと同じルームへ送る
+                let room = state.find_message(reply_to).and_then(|m| m.room.clone());
+                match (&state.nickname, room) {
+                    (Some(nickname), Some(room)) => {
+                        network_manager.send_reply(message, nickname.clone(), room, reply_to)?;
+                    },
+                    _ => state.add_message("The message you replied to is no longer available".to_string()),
+                }
+            },
+            KeyAction::SendPrivateMessage(target, message) => {
                 if let Some(ref nickname) = state.nickname {
-                    network_manager.send_message(message, nickname.clone())?;
+                    network_manager.send_private_message(message, nickname.clone(), target)?;
                 }
             },
+            KeyAction::EditMessage(message_id, message) => {
+                network_manager.send_edit(message_id, message)?;
+            },
+            KeyAction::DeleteMessage(message_id) => {
+                network_manager.send_delete(message_id)?;
+            },
+            KeyAction::React(message_id, emoji) => {
+                network_manager.send_reaction(message_id, emoji)?;
+            },
+            KeyAction::ChangeNickname(nickname) => {
+                network_manager.send_nickname_change(nickname)?;
+            },
+            KeyAction::JoinRoom(room) => {
+                network_manager.send_join(room)?;
+            },
+            KeyAction::LeaveRoom(room) => {
+                network_manager.send_leave(room)?;
+            },
+            KeyAction::SendCommand(command) => {
+                network_manager.send_command(command, state.current_room.clone())?;
+            },
+            KeyAction::ToggleUserList => {
+                state.toggle_user_list();
+                if state.show_user_list {
+                    network_manager.send_info_request("users".to_string())?;
+                }
+            },
+            KeyAction::Typing => {
+                if let (Some(nickname), Some(room)) = (&state.nickname, &state.current_room) {
+                    network_manager.send_typing(nickname.clone(), room.clone())?;
+                }
+            },
+            KeyAction::SetPresence(presence, status) => {
+                network_manager.send_presence(presence, status)?;
+            },
             KeyAction::InfoRequest => {
                 network_manager.send_info_request("connection".to_string())?;
             },
             KeyAction::None => {},
         }
 
-        // ----- 画面描画 -----
-        ui.render(&state)?;
+        // ----- 画面描画（入力が続いている間は後回し） -----
+        if !input_handler.has_pending_input()? {
+            ui.render(&state)?;
+        }
     }
 
     // ----- 後処理 -----
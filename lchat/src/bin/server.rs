@@ -1,14 +1,15 @@
 use std::{
-    io::Result,
+    io::{Error, ErrorKind, Result},
     net::{TcpListener, SocketAddr},
     sync::Arc,
     thread,
     env
 };
 
-use lchat::Packet;
+use lchat::{Packet, DEFAULT_ROOM};
 use lchat::server::{
     client_manager::{self, SharedClients},
+    history::{self, SharedHistory},
     message_handler,
     network,
 };
@@ -17,12 +18,20 @@ fn main() -> Result<()> {
     // コマンドライン引数
     let args: Vec<String> = env::args().collect();
     let port = if args.len() >= 2 { &args[1] } else { "8080" };
+    let replay_count = match args.get(2) {
+        Some(count) => count
+            .parse::<usize>()
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, format!("invalid history count: {count}")))?,
+        None => history::DEFAULT_REPLAY_COUNT,
+    };
 
     // Bind
     let listener = TcpListener::bind(format!("0.0.0.0:{port}"))?;
     println!("Chat server running on 0.0.0.0:{port}");
 
     let clients = client_manager::create_clients_list();
+    let history = history::open_history(history::HISTORY_FILE, replay_count)?;
+    println!("History : {} (replaying last {replay_count} messages on join)", history::HISTORY_FILE);
 
     // 接続待ち
     for stream in listener.incoming() {
@@ -32,7 +41,8 @@ fn main() -> Result<()> {
 
         let client_stream = Arc::new(std::sync::Mutex::new(stream));
         let clients_clone = Arc::clone(&clients);
-        thread::spawn(move || handle_client(client_stream, clients_clone, peer));
+        let history_clone = Arc::clone(&history);
+        thread::spawn(move || handle_client(client_stream, clients_clone, history_clone, peer));
     }
 
     Ok(())
@@ -41,6 +51,7 @@ fn main() -> Result<()> {
 fn handle_client(
     stream: Arc<std::sync::Mutex<std::net::TcpStream>>,
     clients: SharedClients,
+    history: SharedHistory,
     peer: SocketAddr,
 ) -> Result<()> {
     // ----- ニックネーム要求 -----
@@ -49,24 +60,29 @@ fn handle_client(
         Err(e) => eprintln!("{peer} : failed to request nickname: {e}"),
     };
 
-    // ----- 受信一行目をニックネームにする -----
-    let nickname = network::get_nickname(&stream)?;
-
-    // ---- 共有クライアントリストへ登録 ----
-    client_manager::add_client(&clients, Arc::clone(&stream), nickname.clone());
+    // ----- ニックネームを受理して共有クライアントリストへ登録 -----
+    let nickname = network::get_nickname(&stream, &clients)?;
 
-    // ----- 入室通知 -----
-    let join_packet = Packet::join(nickname.clone());
-    network::broadcast(&clients, &join_packet)?;
+    // ----- デフォルトルームへ入室・通知 -----
+    let join_packet = Packet::join(nickname.clone(), DEFAULT_ROOM.to_string()).stamped();
+    message_handler::join_and_replay(&clients, &history, &stream, join_packet, DEFAULT_ROOM)?;
+    network::broadcast_user_list(&clients)?;
 
     // ----- メッセージ転送 -----
-    message_handler::handle_client_messages(&stream, &clients, &nickname)?;
+    if let Err(e) = message_handler::handle_client_messages(&stream, &clients, &history) {
+        eprintln!("{peer} : connection error: {e}");
+    }
 
-    // ----- 退出処理 -----
-    client_manager::remove_client(&clients, &stream);
-    let leave_packet = Packet::leave(nickname.clone());
-    network::broadcast(&clients, &leave_packet)?;
-    println!("{peer} ({nickname}) disconnected");
+    // ----- 退出処理（接続中にニックネームが変わっている場合がある） -----
+    let Some(client) = client_manager::remove_client(&clients, &stream) else {
+        return Ok(());
+    };
+    for room in &client.rooms {
+        let leave_packet = Packet::leave(client.name.clone(), room.clone()).stamped();
+        history::publish(&history, leave_packet, |packet| network::broadcast_to_room(&clients, room, packet))?;
+    }
+    network::broadcast_user_list(&clients)?;
+    println!("{peer} ({}) disconnected", client.name);
 
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,182 @@
+use crate::{MessageKind, Presence};
+use crate::client::{
+    input::KeyAction,
+    ui::AppState,
+};
+
+/// スラッシュコマンドの定義
+pub struct Command {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub help: &'static str,
+    /// 引数を解釈してアクションを返す（引数が不正ならNone）
+    handler: fn(args: &str, state: &mut AppState) -> Option<KeyAction>,
+}
+
+/// 登録済みのコマンド一覧
+pub const COMMANDS: &[Command] = &[
+    Command { name: "help", usage: "/help", help: "Show available commands", handler: help },
+    Command { name: "quit", usage: "/quit", help: "Exit the application", handler: quit },
+    Command { name: "clear", usage: "/clear", help: "Clear the chat history", handler: clear },
+    Command { name: "join", usage: "/join <room>", help: "Join or switch to a room", handler: join },
+    Command { name: "part", usage: "/part [room]", help: "Leave a room (default: current room)", handler: part },
+    Command { name: "me", usage: "/me <action>", help: "Describe an action (* you waves)", handler: me },
+    Command { name: "notice", usage: "/notice <text>", help: "Send a notice to the room", handler: notice },
+    Command { name: "msg", usage: "/msg <nick> <text>", help: "Send a private message", handler: msg },
+    Command { name: "nick", usage: "/nick <name>", help: "Change your nickname", handler: nick },
+    Command { name: "edit", usage: "/edit <id|last> <text>", help: "Edit your message (F3 shows IDs)", handler: edit },
+    Command { name: "delete", usage: "/delete <id|last>", help: "Delete your message", handler: delete },
+    Command { name: "react", usage: "/react [id] <emoji>", help: "React to a message (default: selected)", handler: react },
+    Command { name: "away", usage: "/away [message]", help: "Mark yourself away (again to come back)", handler: away },
+    Command { name: "busy", usage: "/busy [message]", help: "Mark yourself busy", handler: busy },
+    Command { name: "back", usage: "/back", help: "Mark yourself online again", handler: back },
+    Command { name: "connections", usage: "/connections", help: "Show the number of connected users", handler: connections },
+];
+
+/// 入力内容を解釈
+///
+/// `/` で始まる入力はコマンドとして実行し、`//` で始まる入力は先頭の `/` を1つ除いてそのまま送信する。
+/// クライアントに登録されていないコマンドはサーバーへ送信する
+pub fn parse_input(input: String, state: &mut AppState) -> KeyAction {
+    let Some(body) = input.strip_prefix('/') else {
+        return KeyAction::SendMessage(input);
+    };
+    if body.starts_with('/') {
+        return KeyAction::SendMessage(body.to_string());
+    }
+
+    let (name, args) = body.split_once(char::is_whitespace).unwrap_or((body, ""));
+    let Some(command) = COMMANDS.iter().find(|c| c.name == name) else {
+        return KeyAction::SendCommand(body.to_string());
+    };
+
+    match (command.handler)(args.trim(), state) {
+        Some(action) => action,
+        None => {
+            state.add_message(format!("Usage: {}", command.usage));
+            KeyAction::None
+        },
+    }
+}
+
+fn help(_args: &str, state: &mut AppState) -> Option<KeyAction> {
+    state.add_message("Commands:".to_string());
+    for command in COMMANDS {
+        state.add_message(format!("  {:<24} {}", command.usage, command.help));
+    }
+    state.add_message("  Start a message with // to send a literal '/'".to_string());
+    // サーバー側のコマンド一覧も続けて表示する
+    Some(KeyAction::SendCommand("help".to_string()))
+}
+
+fn quit(_args: &str, _state: &mut AppState) -> Option<KeyAction> {
+    Some(KeyAction::Exit)
+}
+
+fn clear(_args: &str, _state: &mut AppState) -> Option<KeyAction> {
+    Some(KeyAction::ClearChat)
+}
+
+fn join(args: &str, _state: &mut AppState) -> Option<KeyAction> {
+    let room = args.split_whitespace().next()?;
+    Some(KeyAction::JoinRoom(room.trim_start_matches('#').to_string()))
+}
+
+fn part(args: &str, state: &mut AppState) -> Option<KeyAction> {
+    let room = match args.split_whitespace().next() {
+        Some(room) => room.trim_start_matches('#').to_string(),
+        None => state.current_room.clone()?,
+    };
+    Some(KeyAction::LeaveRoom(room))
+}
+
+fn me(args: &str, _state: &mut AppState) -> Option<KeyAction> {
+    if args.is_empty() {
+        return None;
+    }
+    Some(KeyAction::SendMessageWithKind(MessageKind::Action, args.to_string()))
+}
+
+fn notice(args: &str, _state: &mut AppState) -> Option<KeyAction> {
+    if args.is_empty() {
+        return None;
+    }
+    Some(KeyAction::SendMessageWithKind(MessageKind::Notice, args.to_string()))
+}
+
+fn msg(args: &str, _state: &mut AppState) -> Option<KeyAction> {
+    let (target, text) = args.split_once(char::is_whitespace)?;
+    let text = text.trim_start();
+    if text.is_empty() {
+        return None;
+    }
+    Some(KeyAction::SendPrivateMessage(target.to_string(), text.to_string()))
+}
+
+fn nick(args: &str, _state: &mut AppState) -> Option<KeyAction> {
+    let name = args.split_whitespace().next()?;
+    Some(KeyAction::ChangeNickname(name.to_string()))
+}
+
+fn edit(args: &str, state: &mut AppState) -> Option<KeyAction> {
+    let (id, text) = args.split_once(char::is_whitespace)?;
+    let text = text.trim_start();
+    if text.is_empty() {
+        return None;
+    }
+    let action = message_id(id, state).map(|id| KeyAction::EditMessage(id, text.to_string()));
+    Some(action.unwrap_or(KeyAction::None))
+}
+
+fn delete(args: &str, state: &mut AppState) -> Option<KeyAction> {
+    let id = args.split_whitespace().next()?;
+    let action = message_id(id, state).map(KeyAction::DeleteMessage);
+    Some(action.unwrap_or(KeyAction::None))
+}
+
+fn react(args: &str, state: &mut AppState) -> Option<KeyAction> {
+    let mut words = args.split_whitespace();
+    let action = match (words.next()?, words.next()) {
+        (id, Some(emoji)) => message_id(id, state).map(|id| KeyAction::React(id, emoji.to_string())),
+        // IDを省略した場合は選択中のメッセージにリアクションする
+        (emoji, None) => {
+            let id = state.selected_message?;
+            state.clear_selection();
+            Some(KeyAction::React(id, emoji.to_string()))
+        },
+    };
+    Some(action.unwrap_or(KeyAction::None))
+}
+
+/// メッセージIDを解釈（`last`は自分の最新のメッセージ）。見つからなければ通知してNone
+fn message_id(arg: &str, state: &mut AppState) -> Option<u64> {
+    let id = match arg {
+        "last" => state.last_own_message_id(),
+        _ => arg.trim_start_matches('[').trim_end_matches(']').parse().ok(),
+    };
+    if id.is_none() {
+        state.add_message(format!("No message found for '{}'", arg));
+    }
+    id
+}
+
+fn away(args: &str, state: &mut AppState) -> Option<KeyAction> {
+    // 離席中に説明なしで実行すると在席に戻る
+    if args.is_empty() && state.presence == Presence::Away {
+        return back(args, state);
+    }
+    Some(KeyAction::SetPresence(Presence::Away, args.to_string()))
+}
+
+fn busy(args: &str, _state: &mut AppState) -> Option<KeyAction> {
+    Some(KeyAction::SetPresence(Presence::Busy, args.to_string()))
+}
+
+fn back(_args: &str, state: &mut AppState) -> Option<KeyAction> {
+    state.auto_away = false;
+    Some(KeyAction::SetPresence(Presence::Online, String::new()))
+}
+
+fn connections(_args: &str, _state: &mut AppState) -> Option<KeyAction> {
+    Some(KeyAction::InfoRequest)
+}
@@ -0,0 +1,95 @@
+use crate::client::command::COMMANDS;
+
+/// Tabによる補完の状態（続けて押すと候補を順に切り替える）
+pub struct Completion {
+    /// 補完対象の単語の開始位置（入力中文字列のバイト位置）
+    pub start: usize,
+    pub candidates: Vec<String>,
+    /// 現在挿入している候補の位置
+    pub index: usize,
+}
+
+impl Completion {
+    /// カーソル位置の単語に対する補完候補を作成（候補がなければNone）
+    ///
+    /// 入力の先頭の`/`で始まる単語はコマンド名、それ以外はニックネームを補完する
+    pub fn new(input: &str, cursor: usize, nicknames: &[String]) -> Option<Self> {
+        let start = input[..cursor]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let word = &input[start..cursor];
+
+        let candidates: Vec<String> = match word.strip_prefix('/') {
+            Some(prefix) if start == 0 => COMMANDS
+                .iter()
+                .filter(|c| c.name.starts_with(prefix))
+                .map(|c| format!("/{} ", c.name))
+                .collect(),
+            _ => {
+                let (mention, prefix) = match word.strip_prefix('@') {
+                    Some(prefix) => ("@", prefix),
+                    None => ("", word),
+                };
+                let prefix = prefix.to_lowercase();
+                // 行頭では呼びかけとして `nick: ` の形で補完する
+                let suffix = if start == 0 { ": " } else { " " };
+                nicknames
+                    .iter()
+                    .filter(|nick| nick.to_lowercase().starts_with(&prefix))
+                    .map(|nick| format!("{}{}{}", mention, nick, suffix))
+                    .collect()
+            },
+        };
+
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(Self { start, candidates, index: 0 })
+    }
+
+    /// 現在の候補
+    pub fn current(&self) -> &str {
+        &self.candidates[self.index]
+    }
+
+    /// 次（`forward`がfalseなら前）の候補に切り替える
+    pub fn cycle(&mut self, forward: bool) {
+        let len = self.candidates.len();
+        self.index = if forward { (self.index + 1) % len } else { (self.index + len - 1) % len };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nicknames() -> Vec<String> {
+        vec!["Alice".to_string(), "alex".to_string(), "bob".to_string()]
+    }
+
+    #[test]
+    fn completes_nicknames_case_insensitively() {
+        let mut completion = Completion::new("al", 2, &nicknames()).unwrap();
+        assert_eq!(completion.current(), "Alice: ");
+        completion.cycle(true);
+        assert_eq!(completion.current(), "alex: ");
+        completion.cycle(true);
+        assert_eq!(completion.current(), "Alice: ");
+    }
+
+    #[test]
+    fn keeps_mentions_and_uses_space_after_first_word() {
+        let completion = Completion::new("hi @B", 5, &nicknames()).unwrap();
+        assert_eq!(completion.start, 3);
+        assert_eq!(completion.current(), "@bob ");
+    }
+
+    #[test]
+    fn completes_commands_only_at_line_start() {
+        let completion = Completion::new("/jo", 3, &nicknames()).unwrap();
+        assert_eq!(completion.current(), "/join ");
+        assert!(Completion::new("x /jo", 5, &nicknames()).is_none());
+    }
+}
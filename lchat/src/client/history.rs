@@ -0,0 +1,171 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+};
+
+/// 保持する入力履歴の最大件数
+const MAX_INPUT_HISTORY: usize = 500;
+
+/// 設定すると入力履歴をファイルに保存しない環境変数
+pub const NO_HISTORY_FILE_ENV: &str = "LCHAT_NO_INPUT_HISTORY";
+
+/// パスワードを含むため履歴に残さないコマンド
+const SECRET_COMMANDS: &[&str] = &["oper"];
+
+/// 送信した入力の履歴（Up/Downで呼び出し、Ctrl-Rで検索）
+#[derive(Default)]
+pub struct InputHistory {
+    entries: Vec<String>,
+    /// 呼び出し中の履歴の位置（Noneなら編集中の入力）
+    index: Option<usize>,
+    /// 履歴を呼び出す前に編集していた入力
+    draft: String,
+    /// 保存先ファイル（Noneなら保存しない）
+    path: Option<PathBuf>,
+}
+
+/// Ctrl-Rによる逆方向インクリメンタル検索の状態
+#[derive(Default)]
+pub struct HistorySearch {
+    pub query: String,
+    /// 一致した履歴の位置
+    pub position: Option<usize>,
+}
+
+impl InputHistory {
+    /// 設定ディレクトリの履歴ファイルを読み込む（読み込めなければ空の履歴）
+    pub fn load() -> Self {
+        let path = env::var_os(NO_HISTORY_FILE_ENV)
+            .is_none()
+            .then(history_file_path)
+            .flatten();
+        let mut history = Self { path, ..Self::default() };
+
+        if let Some(file) = history.path.as_ref().and_then(|p| File::open(p).ok()) {
+            // 1行に1件、JSON文字列として保存している
+            history.entries = BufReader::new(file)
+                .lines()
+                .map_while(|line| line.ok())
+                .filter_map(|line| serde_json::from_str::<String>(&line).ok())
+                .collect();
+            if history.entries.len() > MAX_INPUT_HISTORY {
+                history.entries.drain(..history.entries.len() - MAX_INPUT_HISTORY);
+                history.rewrite();
+            }
+        }
+        history
+    }
+
+    /// 送信した入力を記録（空行・直前と同じ入力・パスワードを含むコマンドは記録しない）
+    pub fn push(&mut self, input: &str) {
+        self.index = None;
+        self.draft.clear();
+        if input.trim().is_empty() || is_secret(input) || self.entries.last().is_some_and(|last| last == input) {
+            return;
+        }
+
+        self.entries.push(input.to_string());
+        if self.entries.len() > MAX_INPUT_HISTORY {
+            self.entries.remove(0);
+        }
+        self.append(input);
+    }
+
+    /// 1つ前の履歴を取得（Up）。初回は編集中の入力を退避する
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        let index = match self.index {
+            Some(0) => return None,
+            Some(i) => i - 1,
+            None => {
+                self.draft = current.to_string();
+                self.entries.len().checked_sub(1)?
+            },
+        };
+        self.index = Some(index);
+        Some(&self.entries[index])
+    }
+
+    /// 1つ後の履歴を取得（Down）。最新を越えると退避していた入力に戻る
+    pub fn newer(&mut self) -> Option<&str> {
+        let index = self.index?;
+        if index + 1 < self.entries.len() {
+            self.index = Some(index + 1);
+            Some(&self.entries[index + 1])
+        } else {
+            self.index = None;
+            Some(&self.draft)
+        }
+    }
+
+    /// `before`より前で`query`を含む最も新しい履歴の位置を検索
+    pub fn search(&self, query: &str, before: Option<usize>) -> Option<usize> {
+        let end = before.unwrap_or(self.entries.len());
+        self.entries[..end].iter().rposition(|entry| entry.contains(query))
+    }
+
+    pub fn get(&self, position: usize) -> Option<&str> {
+        self.entries.get(position).map(String::as_str)
+    }
+
+    fn append(&self, input: &str) {
+        let Some(path) = self.path.as_ref() else {
+            return;
+        };
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| OpenOptions::new().create(true).append(true).open(path))
+            .and_then(|mut file| writeln!(file, "{}", serde_json::to_string(input).unwrap_or_default()));
+        // 画面表示を崩さないよう、保存の失敗は無視する
+        result.ok();
+    }
+
+    fn rewrite(&self) {
+        let Some(path) = self.path.as_ref() else {
+            return;
+        };
+        let lines: Vec<String> = self
+            .entries
+            .iter()
+            .filter_map(|entry| serde_json::to_string(entry).ok())
+            .collect();
+        fs::write(path, lines.join("\n") + "\n").ok();
+    }
+}
+
+/// パスワードを含むコマンドか（大文字小文字は区別せず、`//oper` のような通常のメッセージは含まない）
+fn is_secret(input: &str) -> bool {
+    input
+        .trim_start()
+        .strip_prefix('/')
+        .and_then(|body| body.split_whitespace().next())
+        .is_some_and(|name| SECRET_COMMANDS.iter().any(|secret| secret.eq_ignore_ascii_case(name)))
+}
+
+/// 入力履歴ファイルのパス（$XDG_CONFIG_HOME/lchat/input_history など）
+fn history_file_path() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("lchat").join("input_history"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_commands_are_not_recorded() {
+        let mut history = InputHistory::default();
+        history.push("/oper hunter2");
+        history.push("/OPER hunter2");
+        history.push("//oper is a command");
+        history.push("/join dev");
+        assert_eq!(history.older(""), Some("/join dev"));
+        assert_eq!(history.older(""), Some("//oper is a command"));
+        assert_eq!(history.older(""), None);
+    }
+}
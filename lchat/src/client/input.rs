@@ -1,9 +1,32 @@
-use std::{time::Duration, io::Result};
+use std::{
+    env,
+    io::Result,
+    time::{Duration, Instant},
+};
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseEvent, MouseEventKind},
 };
 
-use crate::client::ui::AppState;
+use crate::{MessageKind, Presence};
+use crate::client::{command, ui::AppState};
+
+/// マウスホイール1回分のスクロール行数
+const WHEEL_SCROLL_LINES: usize = 3;
+
+/// 送信前に確認する貼り付けの行数（これを超えると確認する）
+const DEFAULT_PASTE_CONFIRM_LINES: usize = 5;
+
+/// 貼り付けの確認行数を指定する環境変数
+pub const PASTE_CONFIRM_LINES_ENV: &str = "LCHAT_PASTE_CONFIRM_LINES";
+
+/// 入力中の通知を送る最短の間隔
+const TYPING_INTERVAL: Duration = Duration::from_secs(3);
+
+/// 自動的に離席にするまでの既定の時間（分）
+const DEFAULT_AUTO_AWAY_MINUTES: u64 = 10;
+
+/// 自動離席までの時間（分、0なら無効）を指定する環境変数
+pub const AUTO_AWAY_MINUTES_ENV: &str = "LCHAT_AUTO_AWAY_MINUTES";
 
 /// キーボード入力に対するアクション
 #[derive(Debug)]
@@ -11,31 +34,146 @@ pub enum KeyAction {
     Exit,
     ClearChat,
     SendMessage(String),
+    /// 種類付きのメッセージ（種類, 本文）
+    SendMessageWithKind(MessageKind, String),
     SendNickname(String),
+    SendPrivateMessage(String, String),
+    /// 選択したメッセージへの返信（返信先ID, 本文）
+    SendReply(u64, String),
+    EditMessage(u64, String),
+    DeleteMessage(u64),
+    /// リアクションの追加・取り消し（メッセージID, 絵文字）
+    React(u64, String),
+    ChangeNickname(String),
+    JoinRoom(String),
+    LeaveRoom(String),
+    SendCommand(String),
+    ToggleUserList,
     InfoRequest,
+    /// 入力中であることを通知
+    Typing,
+    /// 在席状況の変更（状況, 説明）
+    SetPresence(Presence, String),
     None,
 }
 
 /// キーボード入力処理
-pub struct InputHandler;
+pub struct InputHandler {
+    /// この行数を超える貼り付けは送信前に確認する
+    paste_confirm_lines: usize,
+    /// 最後に入力中の通知を送った時刻
+    last_typing: Option<Instant>,
+    /// 最後にキー入力があった時刻
+    last_activity: Instant,
+    /// 操作がないまま自動的に離席にするまでの時間（Noneなら無効）
+    auto_away_after: Option<Duration>,
+}
+
+impl Default for InputHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl InputHandler {
     pub fn new() -> Self {
-        Self
+        let paste_confirm_lines = env::var(PASTE_CONFIRM_LINES_ENV)
+            .ok()
+            .and_then(|lines| lines.parse().ok())
+            .unwrap_or(DEFAULT_PASTE_CONFIRM_LINES);
+        let auto_away_minutes = env::var(AUTO_AWAY_MINUTES_ENV)
+            .ok()
+            .and_then(|minutes| minutes.parse().ok())
+            .unwrap_or(DEFAULT_AUTO_AWAY_MINUTES);
+        Self {
+            paste_confirm_lines,
+            last_typing: None,
+            last_activity: Instant::now(),
+            auto_away_after: (auto_away_minutes > 0).then(|| Duration::from_secs(auto_away_minutes * 60)),
+        }
     }
 
-    pub fn handle_input(&self, state: &mut AppState) -> Result<KeyAction> {
+    /// 未処理の入力イベントが残っているか
+    ///
+    /// IMEで確定した文字列は複数のキーイベントとしてまとめて届くため、
+    /// 残っている間は描画を待つことで途中の状態を表示しないようにする
+    pub fn has_pending_input(&self) -> Result<bool> {
+        event::poll(Duration::ZERO)
+    }
+
+    pub fn handle_input(&mut self, state: &mut AppState) -> Result<KeyAction> {
+        let before = state.input.clone();
+        let action = self.read_input(state)?;
+
+        // 送信したら次の入力ですぐに通知できるようにする
+        if matches!(action, KeyAction::SendMessage(_) | KeyAction::SendMessageWithKind(..) | KeyAction::SendReply(..)) {
+            self.last_typing = None;
+        }
+        // メッセージを編集している間は一定間隔ごとに入力中であることを通知する（コマンドは除く）
+        let is_typing = matches!(action, KeyAction::None)
+            && state.nickname.is_some()
+            && state.input != before
+            && !state.input.is_empty()
+            && !state.input.starts_with('/');
+        if is_typing && self.last_typing.is_none_or(|t| t.elapsed() >= TYPING_INTERVAL) {
+            self.last_typing = Some(Instant::now());
+            return Ok(KeyAction::Typing);
+        }
+        if matches!(action, KeyAction::None) {
+            return Ok(self.auto_away(state));
+        }
+        Ok(action)
+    }
+
+    /// 操作がなければ自動的に離席にし、自動離席中に操作があれば在席に戻す
+    fn auto_away(&self, state: &mut AppState) -> KeyAction {
+        let (Some(after), Some(_)) = (self.auto_away_after, &state.nickname) else {
+            return KeyAction::None;
+        };
+        let idle = self.last_activity.elapsed() >= after;
+        if state.auto_away && !idle {
+            state.auto_away = false;
+            return KeyAction::SetPresence(Presence::Online, String::new());
+        }
+        if !state.auto_away && idle && state.presence == Presence::Online {
+            state.auto_away = true;
+            return KeyAction::SetPresence(Presence::Away, "idle".to_string());
+        }
+        KeyAction::None
+    }
+
+    fn read_input(&mut self, state: &mut AppState) -> Result<KeyAction> {
         if event::poll(Duration::from_millis(10))? {
-            match event::read()? {
+            let event = event::read()?;
+            if matches!(event, Event::Key(_) | Event::Paste(_)) {
+                self.last_activity = Instant::now();
+            }
+            match event {
                 Event::Key(KeyEvent { code, modifiers, kind, .. }) => {
                     // Windows で二重入力を防ぐため、KeyPressのみ処理
                     if kind != KeyEventKind::Press {
                         return Ok(KeyAction::None);
                     }
+
+                    // Tab以外のキーで補完を終了する
+                    if !matches!(code, KeyCode::Tab | KeyCode::BackTab) {
+                        state.completion = None;
+                    }
+
+                    // 履歴検索中は検索用のキー操作として処理
+                    if state.history_search.is_some() {
+                        Self::handle_search_key(code, modifiers, state);
+                        return Ok(KeyAction::None);
+                    }
                     
                     match (code, modifiers) {
                         // 終了系
                         (KeyCode::Char('c'), KeyModifiers::CONTROL) => Ok(KeyAction::Exit),
+                        // メッセージを選択中はEscで選択を解除する
+                        (KeyCode::Esc, _) if state.selected_message.is_some() => {
+                            state.clear_selection();
+                            Ok(KeyAction::None)
+                        },
                         (KeyCode::Esc, _) => Ok(KeyAction::Exit),
 
                         // 移動系
@@ -47,33 +185,167 @@ impl InputHandler {
                             state.move_cursor_right();
                             Ok(KeyAction::None)
                         },
+                        (KeyCode::Home, _) | (KeyCode::Char('a'), KeyModifiers::CONTROL) => {
+                            state.move_cursor_home();
+                            Ok(KeyAction::None)
+                        },
+                        (KeyCode::End, _) | (KeyCode::Char('e'), KeyModifiers::CONTROL) => {
+                            state.move_cursor_end();
+                            Ok(KeyAction::None)
+                        },
+                        (KeyCode::Char('b'), KeyModifiers::ALT) => {
+                            state.move_word_left();
+                            Ok(KeyAction::None)
+                        },
+                        (KeyCode::Char('f'), KeyModifiers::ALT) => {
+                            state.move_word_right();
+                            Ok(KeyAction::None)
+                        },
+
+                        // 履歴系
+                        // メッセージ選択系（選択中にEnterで返信）
+                        (KeyCode::Up, KeyModifiers::ALT) => {
+                            state.select_prev_message();
+                            Ok(KeyAction::None)
+                        },
+                        (KeyCode::Down, KeyModifiers::ALT) => {
+                            state.select_next_message();
+                            Ok(KeyAction::None)
+                        },
+
+                        (KeyCode::Up, _) => {
+                            state.history_prev();
+                            Ok(KeyAction::None)
+                        },
+                        (KeyCode::Down, _) => {
+                            state.history_next();
+                            Ok(KeyAction::None)
+                        },
+                        (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+                            state.search_history();
+                            Ok(KeyAction::None)
+                        },
+
+                        // スクロール系
+                        (KeyCode::PageUp, _) => {
+                            state.page_up();
+                            Ok(KeyAction::None)
+                        },
+                        (KeyCode::PageDown, _) => {
+                            state.page_down();
+                            Ok(KeyAction::None)
+                        },
 
                         // 編集系
                         (KeyCode::Char('l'), KeyModifiers::CONTROL) => Ok(KeyAction::ClearChat),
+                        (KeyCode::Char('n'), KeyModifiers::CONTROL) => {
+                            state.next_room();
+                            Ok(KeyAction::None)
+                        },
                         (KeyCode::Backspace, _) => {
                             state.backspace();
                             Ok(KeyAction::None)
                         },
+                        (KeyCode::Delete, _) => {
+                            state.delete();
+                            Ok(KeyAction::None)
+                        },
+                        (KeyCode::Char('w'), KeyModifiers::CONTROL) => {
+                            state.kill_word_before();
+                            Ok(KeyAction::None)
+                        },
+                        (KeyCode::Char('u'), KeyModifiers::CONTROL) => {
+                            state.kill_to_start();
+                            Ok(KeyAction::None)
+                        },
+                        (KeyCode::Char('k'), KeyModifiers::CONTROL) => {
+                            state.kill_to_end();
+                            Ok(KeyAction::None)
+                        },
+                        (KeyCode::Char('y'), KeyModifiers::CONTROL) => {
+                            state.yank();
+                            Ok(KeyAction::None)
+                        },
+                        (KeyCode::Char('j'), KeyModifiers::CONTROL) => {
+                            state.insert_newline();
+                            Ok(KeyAction::None)
+                        },
                         (KeyCode::Char(c), _) => {
                             state.insert_char(c);
                             Ok(KeyAction::None)
                         },
 
                         // 送信系
+                        (KeyCode::Enter, m) if m.intersects(KeyModifiers::ALT | KeyModifiers::SHIFT) => {
+                            state.insert_newline();
+                            Ok(KeyAction::None)
+                        },
+                        (KeyCode::Enter, _) if state.large_paste && state.nickname.is_some() => {
+                            // 大きな貼り付けは一度確認し、もう一度Enterで送信する
+                            state.large_paste = false;
+                            let lines = state.input.split('\n').count();
+                            state.add_message(format!("Send {} lines? Press Enter again to send, or keep editing.", lines));
+                            Ok(KeyAction::None)
+                        },
                         (KeyCode::Enter, _) => {
                             let input = state.take_input();
                             if state.nickname.is_none() {
-                                state.nickname = Some(input.clone());
-                                Ok(KeyAction::SendNickname(input))
+                                let nickname = input.trim().to_string();
+                                state.nickname = Some(nickname.clone());
+                                Ok(KeyAction::SendNickname(nickname))
+                            } else if input.trim().is_empty() {
+                                Ok(KeyAction::None)
                             } else {
-                                Ok(KeyAction::SendMessage(input))
+                                state.input_history.push(&input);
+                                // メッセージを選択中なら返信として送信する
+                                match (command::parse_input(input, state), state.selected_message) {
+                                    (KeyAction::SendMessage(message), Some(reply_to)) => {
+                                        state.clear_selection();
+                                        Ok(KeyAction::SendReply(reply_to, message))
+                                    },
+                                    (action, _) => Ok(action),
+                                }
                             }
                         },
-                        (KeyCode::Tab, _) => Ok(KeyAction::InfoRequest),
+                        (KeyCode::Tab, _) => {
+                            state.complete(true);
+                            Ok(KeyAction::None)
+                        },
+                        (KeyCode::BackTab, _) => {
+                            state.complete(false);
+                            Ok(KeyAction::None)
+                        },
+                        (KeyCode::F(2), _) => Ok(KeyAction::ToggleUserList),
+                        (KeyCode::F(3), _) => {
+                            state.toggle_message_ids();
+                            Ok(KeyAction::None)
+                        },
 
                         _ => Ok(KeyAction::None),
                     }
                 },
+                Event::Paste(text) => {
+                    // 改行を含む貼り付けも1回の挿入として扱い、途中で送信しない
+                    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+                    state.completion = None;
+                    if state.history_search.is_some() {
+                        text.chars().filter(|&c| c != '\n').for_each(|c| state.search_insert_char(c));
+                    } else {
+                        state.insert_str(&text);
+                        if text.split('\n').count() > self.paste_confirm_lines {
+                            state.large_paste = true;
+                        }
+                    }
+                    Ok(KeyAction::None)
+                },
+                Event::Mouse(MouseEvent { kind, .. }) => {
+                    match kind {
+                        MouseEventKind::ScrollUp => state.scroll_up(WHEEL_SCROLL_LINES),
+                        MouseEventKind::ScrollDown => state.scroll_down(WHEEL_SCROLL_LINES),
+                        _ => {},
+                    }
+                    Ok(KeyAction::None)
+                },
                 Event::Resize(c, r) => {
                     state.update_size(c, r);
                     Ok(KeyAction::None)
@@ -84,4 +356,18 @@ impl InputHandler {
             Ok(KeyAction::None)
         }
     }
+
+    /// 履歴検索中のキー操作
+    fn handle_search_key(code: KeyCode, modifiers: KeyModifiers, state: &mut AppState) {
+        match (code, modifiers) {
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => state.search_history(),
+            (KeyCode::Char('g') | KeyCode::Char('c'), KeyModifiers::CONTROL) | (KeyCode::Esc, _) => {
+                state.cancel_search();
+            },
+            (KeyCode::Backspace, _) => state.search_backspace(),
+            (KeyCode::Char(c), m) if !m.contains(KeyModifiers::CONTROL) => state.search_insert_char(c),
+            // Enterやカーソル移動で一致した履歴を確定する
+            _ => state.accept_search(),
+        }
+    }
 }
\ No newline at end of file
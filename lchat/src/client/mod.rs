@@ -1,3 +1,7 @@
 pub mod ui;
 pub mod input;
-pub mod network;
\ No newline at end of file
+pub mod command;
+pub mod completion;
+pub mod history;
+pub mod network;
+pub mod text;
\ No newline at end of file
@@ -6,7 +6,7 @@ use std::{
     thread,
 };
 
-use crate::{Packet, PacketType};
+use crate::{MessageKind, Packet, Presence};
 
 /// ネットワーク管理
 pub struct NetworkManager {
@@ -14,20 +14,45 @@ pub struct NetworkManager {
 }
 
 impl NetworkManager {
-    pub fn new(addr: String) -> Result<(Self, mpsc::Receiver<String>)> {
+    pub fn new(addr: String) -> Result<(Self, mpsc::Receiver<Packet>)> {
         let tcp = TcpStream::connect(addr)?;
         let writer = tcp.try_clone()?;
         let reader = tcp;
 
         // 受信用スレッド
-        let (tx, rx) = mpsc::channel::<String>();
+        let (tx, rx) = mpsc::channel::<Packet>();
         recv_thread(reader, tx);
 
         Ok((Self { writer }, rx))
     }
 
-    pub fn send_message(&mut self, message: String, nickname: String) -> Result<()> {
-        let packet = Packet::message(message, nickname);
+    pub fn send_message(&mut self, message: String, nickname: String, room: String) -> Result<()> {
+        let packet = Packet::message(message, nickname, room);
+        self.send_packet(&packet)
+    }
+
+    pub fn send_message_with_kind(&mut self, message: String, nickname: String, room: String, kind: MessageKind) -> Result<()> {
+        let packet = Packet::message_with_kind(message, nickname, room, kind);
+        self.send_packet(&packet)
+    }
+
+    pub fn send_reply(&mut self, message: String, nickname: String, room: String, reply_to: u64) -> Result<()> {
+        let packet = Packet::reply(message, nickname, room, reply_to);
+        self.send_packet(&packet)
+    }
+
+    pub fn send_private_message(&mut self, message: String, nickname: String, target: String) -> Result<()> {
+        let packet = Packet::private_message(message, nickname, target);
+        self.send_packet(&packet)
+    }
+
+    pub fn send_join(&mut self, room: String) -> Result<()> {
+        let packet = Packet::join_request(room);
+        self.send_packet(&packet)
+    }
+
+    pub fn send_leave(&mut self, room: String) -> Result<()> {
+        let packet = Packet::leave_request(room);
         self.send_packet(&packet)
     }
     
@@ -36,11 +61,46 @@ impl NetworkManager {
         self.send_packet(&packet)
     }
 
+    pub fn send_nickname_change(&mut self, nickname: String) -> Result<()> {
+        let packet = Packet::nickname_change_request(nickname);
+        self.send_packet(&packet)
+    }
+
+    pub fn send_edit(&mut self, message_id: u64, content: String) -> Result<()> {
+        let packet = Packet::edit_request(message_id, content);
+        self.send_packet(&packet)
+    }
+
+    pub fn send_delete(&mut self, message_id: u64) -> Result<()> {
+        let packet = Packet::delete_request(message_id);
+        self.send_packet(&packet)
+    }
+
+    pub fn send_reaction(&mut self, message_id: u64, emoji: String) -> Result<()> {
+        let packet = Packet::reaction_request(message_id, emoji);
+        self.send_packet(&packet)
+    }
+
+    pub fn send_typing(&mut self, nickname: String, room: String) -> Result<()> {
+        let packet = Packet::typing(nickname, room);
+        self.send_packet(&packet)
+    }
+
+    pub fn send_presence(&mut self, presence: Presence, status: String) -> Result<()> {
+        let packet = Packet::presence_request(presence, status);
+        self.send_packet(&packet)
+    }
+
     pub fn send_info_request(&mut self, info: String) -> Result<()> {
         let packet = Packet::info_request(info);
         self.send_packet(&packet)
     }
 
+    pub fn send_command(&mut self, command: String, room: Option<String>) -> Result<()> {
+        let packet = Packet::command(command, room);
+        self.send_packet(&packet)
+    }
+
     fn send_packet(&mut self, packet: &Packet) -> Result<()> {
         if let Ok(json) = packet.to_json() {
             self.writer.write_all(format!("{}\n", json).as_bytes())?;
@@ -66,7 +126,7 @@ pub fn get_connection_address() -> String {
 }
 
 /// 受信スレッドを開始
-pub fn recv_thread(reader: TcpStream, tx: mpsc::Sender<String>) {
+pub fn recv_thread(reader: TcpStream, tx: mpsc::Sender<Packet>) {
     thread::spawn(move || {
         let mut buf_reader = BufReader::new(reader);
         let mut line = String::new();
@@ -80,31 +140,7 @@ pub fn recv_thread(reader: TcpStream, tx: mpsc::Sender<String>) {
             }
 
             if let Ok(packet) = Packet::from_json(trimmed) {
-                match packet.packet_type {
-                    PacketType::NicknameRequest => {
-                        tx.send(packet.content).ok();
-                    },
-                    PacketType::NicknameResponse => {
-                        // ニックネーム応答は通常表示しない
-                    },
-                    PacketType::Message => {
-                        if let Some(nickname) = packet.nickname {
-                            tx.send(format!("{} : {}", nickname, packet.content)).ok();
-                        }
-                    },
-                    PacketType::Join | PacketType::Leave => {
-                        tx.send(packet.content).ok();
-                    },
-                    PacketType::InfoRequest => {
-                        eprintln!("This is the packet type sent by the client.")
-                    },
-                    PacketType::Connection => {
-                        tx.send(format!("Connected clients: {}", packet.content)).ok();
-                    },
-                    PacketType::Error => {
-                        tx.send(format!("Error: {}", packet.content)).ok();
-                    },
-                }
+                tx.send(packet).ok();
             } else {
                 eprintln!("Failed to parse JSON: {:?}", trimmed);
             }
@@ -0,0 +1,205 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// 文字列の表示幅（全角文字は2）
+pub fn display_width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
+
+/// 文字の表示幅（制御文字は0）
+pub fn char_width(c: char) -> usize {
+    UnicodeWidthChar::width(c).unwrap_or(0)
+}
+
+/// 表示幅に合わせてテキストを折り返す
+///
+/// 改行・空白の後・全角文字の前後で折り返し、収まらない長い単語は途中で区切る
+pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut rows = Vec::new();
+    let mut row: Vec<char> = Vec::new();
+    let mut row_width = 0;
+    // 次の行をこの位置から始められる（rowのインデックス）
+    let mut break_at: Option<usize> = None;
+    // 直前の折り返しが改行ではなく幅によるものか
+    let mut soft_wrapped = false;
+
+    for c in text.chars() {
+        // 改行は常に折り返す
+        if c == '\n' {
+            rows.push(row.drain(..).collect::<String>().trim_end().to_string());
+            row_width = 0;
+            break_at = None;
+            soft_wrapped = false;
+            continue;
+        }
+
+        let w = char_width(c);
+        if row_width + w > width && !row.is_empty() {
+            let split = if c == ' ' || w > 1 {
+                row.len()
+            } else {
+                break_at.filter(|&i| i > 0).unwrap_or(row.len())
+            };
+            let rest: Vec<char> = row.split_off(split).into_iter().skip_while(|&c| c == ' ').collect();
+            rows.push(row.iter().collect::<String>().trim_end().to_string());
+            row = rest;
+            row_width = row.iter().map(|&c| char_width(c)).sum();
+            break_at = None;
+            soft_wrapped = true;
+        }
+        // 幅で折り返した行の先頭の空白は表示しない（改行後の字下げは残す）
+        if c == ' ' && row.is_empty() && soft_wrapped {
+            continue;
+        }
+
+        row.push(c);
+        row_width += w;
+        if c == ' ' || w > 1 {
+            break_at = Some(row.len());
+        }
+    }
+
+    rows.push(row.into_iter().collect());
+    rows
+}
+
+/// 表示幅に収まるよう末尾を切り詰める
+pub fn truncate_to_width(text: &str, width: usize) -> String {
+    let mut result = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = char_width(c);
+        if used + w > width {
+            break;
+        }
+        used += w;
+        result.push(c);
+    }
+    result
+}
+
+/// バイト位置`index`の直前の書記素クラスタ境界
+pub fn prev_grapheme_boundary(text: &str, index: usize) -> usize {
+    text[..index]
+        .grapheme_indices(true)
+        .next_back()
+        .map_or(0, |(i, _)| i)
+}
+
+/// バイト位置`index`の直後の書記素クラスタ境界
+pub fn next_grapheme_boundary(text: &str, index: usize) -> usize {
+    text[index..]
+        .graphemes(true)
+        .next()
+        .map_or(index, |g| index + g.len())
+}
+
+/// 単語を構成する書記素か（英数字・全角文字・`_`）
+fn is_word_grapheme(grapheme: &str) -> bool {
+    grapheme.chars().next().is_some_and(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// バイト位置`index`より前にある単語の先頭（Alt-B）
+pub fn prev_word_start(text: &str, index: usize) -> usize {
+    let mut graphemes = text[..index].grapheme_indices(true).rev().peekable();
+    while graphemes.next_if(|(_, g)| !is_word_grapheme(g)).is_some() {}
+    let mut start = graphemes.peek().map_or(0, |(i, g)| i + g.len());
+    while let Some((i, _)) = graphemes.next_if(|(_, g)| is_word_grapheme(g)) {
+        start = i;
+    }
+    start
+}
+
+/// バイト位置`index`より後にある単語の末尾（Alt-F）
+pub fn next_word_end(text: &str, index: usize) -> usize {
+    let mut graphemes = text[index..].grapheme_indices(true).peekable();
+    while graphemes.next_if(|(_, g)| !is_word_grapheme(g)).is_some() {}
+    let mut end = graphemes.peek().map_or(text.len() - index, |(i, _)| *i);
+    while let Some((i, g)) = graphemes.next_if(|(_, g)| is_word_grapheme(g)) {
+        end = i + g.len();
+    }
+    index + end
+}
+
+/// バイト位置`index`より前にある空白区切りの単語の先頭（Ctrl-W）
+pub fn prev_whitespace_word_start(text: &str, index: usize) -> usize {
+    let before = text[..index].trim_end();
+    before
+        .char_indices()
+        .rev()
+        .find(|(_, c)| c.is_whitespace())
+        .map_or(0, |(i, c)| i + c.len_utf8())
+}
+
+/// 表示幅`width`を超えない最後の書記素クラスタ境界（行内のバイト位置）
+pub fn offset_at_width(line: &str, width: usize) -> usize {
+    let mut used = 0;
+    for (i, grapheme) in line.grapheme_indices(true) {
+        used += display_width(grapheme);
+        if used > width {
+            return i;
+        }
+    }
+    line.len()
+}
+
+/// 複数行の本文の2行目以降を、先頭行の接頭辞と同じ幅だけ字下げする
+pub fn indent_continuation(prefix: &str, content: &str) -> String {
+    let indent = " ".repeat(display_width(prefix));
+    format!("{}{}", prefix, content.replace('\n', &format!("\n{}", indent)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn width_counts_wide_characters_as_two() {
+        assert_eq!(display_width("abc"), 3);
+        assert_eq!(display_width("日本語"), 6);
+        assert_eq!(truncate_to_width("日本語", 5), "日本");
+    }
+
+    #[test]
+    fn wrap_breaks_after_spaces() {
+        assert_eq!(wrap_text("hello world", 5), ["hello", "world"]);
+        assert_eq!(wrap_text("hello world", 8), ["hello", "world"]);
+    }
+
+    #[test]
+    fn wrap_splits_long_words_and_wide_text() {
+        assert_eq!(wrap_text("abcdefgh", 3), ["abc", "def", "gh"]);
+        assert_eq!(wrap_text("日本語テキスト", 5), ["日本", "語テ", "キス", "ト"]);
+    }
+
+    #[test]
+    fn wrap_keeps_hard_breaks_and_indentation() {
+        assert_eq!(wrap_text("a\n  b", 10), ["a", "  b"]);
+        assert_eq!(wrap_text("", 10), [""]);
+    }
+
+    #[test]
+    fn grapheme_boundaries_keep_combining_marks() {
+        let text = "e\u{301}x";
+        assert_eq!(next_grapheme_boundary(text, 0), 3);
+        assert_eq!(prev_grapheme_boundary(text, 3), 0);
+    }
+
+    #[test]
+    fn word_motion_skips_punctuation() {
+        let text = "foo, bar_baz 日本";
+        assert_eq!(prev_word_start(text, text.len()), 13);
+        assert_eq!(prev_word_start(text, 13), 5);
+        assert_eq!(next_word_end(text, 0), 3);
+        assert_eq!(next_word_end(text, 3), 12);
+        assert_eq!(prev_whitespace_word_start("foo bar  ", 9), 4);
+    }
+
+    #[test]
+    fn offset_at_width_stops_before_overflowing_character() {
+        assert_eq!(offset_at_width("日本語", 3), 3);
+        assert_eq!(offset_at_width("日本語", 4), 6);
+        assert_eq!(offset_at_width("abc", 10), 3);
+    }
+}
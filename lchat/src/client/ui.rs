@@ -1,18 +1,112 @@
-use std::io::{self, Write, Result};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    io::{self, Write, Result},
+    time::{Duration, Instant},
+};
 use crossterm::{
     cursor::MoveTo,
-    style::Print,
+    event::{
+        DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    style::{Attribute, Print, SetAttribute},
     terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
     ExecutableCommand,
     QueueableCommand,
 };
 
+use chrono::format::{Item, StrftimeItems};
+
+use crate::{format_timestamp, MessageKind, Packet, PacketType, Presence, UserInfo};
+use crate::client::{
+    completion::Completion,
+    history::{HistorySearch, InputHistory},
+    text,
+};
+
+/// 保持するチャット履歴の最大行数
+const MAX_SCROLLBACK: usize = 1000;
+
+/// 入力欄の最大行数（これを超える場合は入力欄内でスクロール）
+const MAX_INPUT_LINES: usize = 6;
+
+/// ユーザー一覧サイドバーの最大幅
+const SIDEBAR_MAX_WIDTH: u16 = 20;
+
+/// 入力欄の上のステータス行の高さ
+const STATUS_HEIGHT: u16 = 1;
+
+/// 入力中の通知が途絶えてから表示を消すまでの時間
+const TYPING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 返信に表示する返信先の引用の最大幅
+const QUOTE_MAX_WIDTH: usize = 40;
+
+/// メッセージの時刻の既定の表示形式
+const DEFAULT_TIME_FORMAT: &str = "%H:%M";
+
+/// 時刻の表示形式（strftime形式、空なら表示しない）を指定する環境変数
+pub const TIME_FORMAT_ENV: &str = "LCHAT_TIME_FORMAT";
+
+/// チャット表示の1行分
+pub struct ChatLine {
+    pub text: String,
+    /// メッセージの行なら元のパケット（編集・削除の反映に使う）
+    pub message: Option<Packet>,
+    /// 表示の種類（入退室などのお知らせはSystem）
+    pub kind: Option<MessageKind>,
+}
+
+impl ChatLine {
+    /// メッセージの種類に応じた表示属性（アクションは斜体、通知は太字、お知らせは薄く表示）
+    fn attribute(&self) -> Option<Attribute> {
+        match self.kind? {
+            MessageKind::Action => Some(Attribute::Italic),
+            MessageKind::Notice => Some(Attribute::Bold),
+            MessageKind::System => Some(Attribute::Dim),
+        }
+    }
+}
+
 /// アプリケーションの状態
 pub struct AppState {
-    pub chat_lines: Vec<String>,
+    pub chat_lines: Vec<ChatLine>,
+    /// 最下部から何行上を表示しているか（0なら最新行を追従）
+    pub scroll_offset: usize,
+    /// Alt-Up/Downで選択中のメッセージのID（Enterで返信）
+    pub selected_message: Option<u64>,
+    /// この接続で送信したメッセージのID（ニックネームを変えても /edit last で選べるようにする）
+    own_messages: HashSet<u64>,
     pub input: String,
+    /// 入力中文字列でのカーソルのバイト位置（常に書記素クラスタの境界）
     pub cursor: usize,
+    /// Ctrl-W・Ctrl-U・Ctrl-Kで削除した文字列（Ctrl-Yで貼り付け）
+    pub yank_buffer: String,
+    /// 確認が必要な大きさの貼り付けを含んでいるか（送信前に確認する）
+    pub large_paste: bool,
+    pub input_history: InputHistory,
+    /// Ctrl-Rで履歴を検索中ならSome
+    pub history_search: Option<HistorySearch>,
+    /// Tabで補完中ならSome（他のキーを押すと終了）
+    pub completion: Option<Completion>,
     pub nickname: Option<String>,
+    pub rooms: Vec<String>,
+    pub current_room: Option<String>,
+    /// ルームごとに最後に受信した通し番号
+    pub last_seq: HashMap<String, u64>,
+    pub users: Vec<UserInfo>,
+    /// 自分の在席状況
+    pub presence: Presence,
+    /// 一定時間操作がなく自動的に離席にしたか（操作すると在席に戻す）
+    pub auto_away: bool,
+    /// 入力中のユーザー（ニックネーム, ルーム, 最後に通知を受けた時刻）
+    pub typing: Vec<(String, String, Instant)>,
+    pub show_user_list: bool,
+    /// メッセージIDを表示するか（/edit・/deleteで指定するため）
+    pub show_message_ids: bool,
+    /// メッセージの時刻の表示形式
+    pub time_format: String,
     pub cols: u16,
     pub rows: u16,
 }
@@ -22,59 +116,771 @@ impl AppState {
         let (cols, rows) = terminal::size()?;
         Ok(Self {
             chat_lines: Vec::new(),
+            scroll_offset: 0,
+            selected_message: None,
+            own_messages: HashSet::new(),
             input: String::new(),
             cursor: 0,
+            yank_buffer: String::new(),
+            large_paste: false,
+            input_history: InputHistory::load(),
+            history_search: None,
+            completion: None,
             nickname: None,
+            rooms: Vec::new(),
+            current_room: None,
+            last_seq: HashMap::new(),
+            users: Vec::new(),
+            presence: Presence::Online,
+            auto_away: false,
+            typing: Vec::new(),
+            show_user_list: false,
+            show_message_ids: false,
+            time_format: load_time_format(),
             cols,
             rows,
         })
     }
 
     pub fn add_message(&mut self, message: String) {
-        self.chat_lines.push(message);
-        let limit = self.rows.saturating_sub(1) as usize;
-        if self.chat_lines.len() > limit {
-            self.chat_lines.drain(..self.chat_lines.len() - limit);
+        self.push_line(ChatLine { text: message, message: None, kind: None });
+    }
+
+    /// 入退室・ニックネーム変更などのお知らせを追加
+    fn add_system_message(&mut self, message: String) {
+        self.push_line(ChatLine { text: message, message: None, kind: Some(MessageKind::System) });
+    }
+
+    /// チャットメッセージを追加（編集・削除を反映できるよう元のパケットを保持する）
+    fn add_chat_message(&mut self, packet: Packet) {
+        if let Some(text) = self.format_message(&packet) {
+            self.push_line(ChatLine { text, kind: packet.kind, message: Some(packet) });
+        }
+    }
+
+    fn push_line(&mut self, line: ChatLine) {
+        self.chat_lines.push(line);
+        if self.chat_lines.len() > MAX_SCROLLBACK {
+            self.chat_lines.drain(..self.chat_lines.len() - MAX_SCROLLBACK);
+        }
+        // スクロール中は表示位置を維持する
+        if self.scroll_offset > 0 {
+            self.scroll_offset = (self.scroll_offset + 1).min(self.max_scroll_offset());
+        }
+    }
+
+    /// チャット表示領域の高さ
+    pub fn chat_height(&self) -> u16 {
+        self.rows.saturating_sub(self.input_height() + STATUS_HEIGHT)
+    }
+
+    /// 入力欄の高さ（入力の行数に合わせて広がる）
+    pub fn input_height(&self) -> u16 {
+        let lines = if self.history_search.is_some() { 1 } else { self.input.split('\n').count() };
+        let max = MAX_INPUT_LINES.min(self.rows as usize / 2).max(1);
+        lines.clamp(1, max) as u16
+    }
+
+    /// チャット表示領域の幅（サイドバーを除く）
+    pub fn chat_width(&self) -> u16 {
+        self.cols.saturating_sub(self.sidebar_width())
+    }
+
+    /// 折り返し後の行数を考慮したスクロール量の上限
+    fn max_scroll_offset(&self) -> usize {
+        let height = self.chat_height() as usize;
+        let width = self.chat_width() as usize;
+        let mut rows = 0;
+        for (i, line) in self.chat_lines.iter().enumerate() {
+            rows += text::wrap_text(line.text.trim_end(), width).len();
+            if rows >= height {
+                return self.chat_lines.len() - (i + 1);
+            }
+        }
+        0
+    }
+
+    /// 過去方向へスクロール
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.scroll_offset = (self.scroll_offset + lines).min(self.max_scroll_offset());
+    }
+
+    /// 最新方向へスクロール
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.scroll_offset = self.scroll_offset.saturating_sub(lines);
+    }
+
+    /// 1画面分前へスクロール（PageUp）。表示中の最も古いメッセージが最下行に来る
+    pub fn page_up(&mut self) {
+        let visible_end = self.chat_lines.len() - self.scroll_offset.min(self.chat_lines.len());
+        // 1つのメッセージが画面より高い場合も少なくとも1件は進める
+        let end = (self.first_visible_line() + 1).min(visible_end.saturating_sub(1));
+        self.scroll_offset = (self.chat_lines.len() - end).min(self.max_scroll_offset());
+    }
+
+    /// 1画面分後へスクロール（PageDown）。表示中の最も新しいメッセージが最上行に来る
+    pub fn page_down(&mut self) {
+        let height = self.chat_height() as usize;
+        let width = self.chat_width() as usize;
+        let visible_end = self.chat_lines.len() - self.scroll_offset.min(self.chat_lines.len());
+        let mut rows = 0;
+        let mut end = visible_end;
+        for i in visible_end.saturating_sub(1)..self.chat_lines.len() {
+            rows += text::wrap_text(self.chat_lines[i].text.trim_end(), width).len();
+            if rows > height {
+                break;
+            }
+            end = i + 1;
+        }
+        let end = end.max(visible_end + 1).min(self.chat_lines.len());
+        self.scroll_offset = self.chat_lines.len() - end;
+    }
+
+    /// スクロール中に表示領域より下にある、折り返し後の行数
+    pub fn rows_below(&self) -> usize {
+        let width = self.chat_width() as usize;
+        let visible_end = self.chat_lines.len() - self.scroll_offset.min(self.chat_lines.len());
+        self.chat_lines[visible_end..]
+            .iter()
+            .map(|line| text::wrap_text(line.text.trim_end(), width).len())
+            .sum()
+    }
+
+    /// 受信パケットを状態に反映
+    pub fn handle_packet(&mut self, packet: Packet) {
+        // 受信済みのパケットは再表示しない
+        if !self.check_sequence(&packet) {
+            return;
+        }
+        let time = self.time_prefix(&packet);
+        // 発言・退出したユーザーは入力中ではなくなる
+        if matches!(packet.packet_type, PacketType::Message | PacketType::Leave | PacketType::NicknameChange) {
+            if let (Some(nickname), Some(room)) = (&packet.nickname, &packet.room) {
+                self.typing.retain(|(n, r, _)| n != nickname || r != room);
+            } else if let Some(nickname) = &packet.nickname {
+                self.typing.retain(|(n, _, _)| n != nickname);
+            }
+        }
+        match packet.packet_type {
+            PacketType::NicknameRequest => {
+                // 再要求された場合は改めてニックネームを入力させる
+                self.nickname = None;
+                self.add_message(packet.content);
+            },
+            PacketType::NicknameResponse => {
+                // ニックネーム応答は通常表示しない
+            },
+            PacketType::NicknameChange => {
+                // サーバーが変更を確定した時点で自分のニックネームを更新する
+                if self.is_own(&packet.nickname) {
+                    self.nickname = packet.target;
+                }
+                let line = time + &packet.content;
+                self.add_system_message(line);
+            },
+            PacketType::Message => {
+                // 受信時点のニックネームは接続中の自分だけのもの（履歴の再送は含めない）
+                if let Some(id) = packet.id.filter(|_| self.is_own(&packet.nickname)) {
+                    self.own_messages.insert(id);
+                }
+                self.add_chat_message(packet);
+            },
+            PacketType::Edit | PacketType::Delete => {
+                self.update_message(packet.message_id, |message| {
+                    message.packet_type = packet.packet_type;
+                    message.content = packet.content;
+                });
+            },
+            PacketType::Presence => {
+                let Some(nickname) = packet.nickname else {
+                    return;
+                };
+                let presence = packet.presence.unwrap_or_default();
+                let status = Some(packet.content).filter(|s| !s.is_empty());
+                if let Some(user) = self.users.iter_mut().find(|u| u.nickname == nickname) {
+                    user.presence = presence;
+                    user.status = status.clone();
+                }
+                if self.nickname.as_ref() == Some(&nickname) {
+                    self.presence = presence;
+                }
+                let line = match (presence, status) {
+                    (Presence::Online, _) => format!("*** {} is back ***", nickname),
+                    (presence, Some(status)) => format!("*** {} is now {} ({}) ***", nickname, presence.as_str(), status),
+                    (presence, None) => format!("*** {} is now {} ***", nickname, presence.as_str()),
+                };
+                self.add_system_message(time + &line);
+            },
+            PacketType::Typing => {
+                if let (Some(nickname), Some(room)) = (packet.nickname, packet.room) {
+                    self.typing.retain(|(n, r, at)| (*n != nickname || *r != room) && at.elapsed() < TYPING_TIMEOUT);
+                    self.typing.push((nickname, room, Instant::now()));
+                }
+            },
+            PacketType::Reaction => {
+                self.update_message(packet.message_id, |message| message.reactions = packet.reactions);
+            },
+            PacketType::PrivateMessage => {
+                if let (Some(nickname), Some(target)) = (packet.nickname, packet.target) {
+                    let prefix = format!("{}[DM] {} -> {} : ", time, nickname, target);
+                    self.add_message(text::indent_continuation(&prefix, &packet.content));
+                }
+            },
+            PacketType::Join => {
+                if let Some(room) = packet.room.filter(|_| self.is_own(&packet.nickname)) {
+                    if !self.rooms.contains(&room) {
+                        self.rooms.push(room.clone());
+                    }
+                    self.current_room = Some(room);
+                }
+                let line = time + &packet.content;
+                self.add_system_message(line);
+            },
+            PacketType::Leave => {
+                if let Some(room) = packet.room.filter(|_| self.is_own(&packet.nickname)) {
+                    self.rooms.retain(|r| *r != room);
+                    self.last_seq.remove(&room);
+                    if self.current_room.as_ref() == Some(&room) {
+                        self.current_room = self.rooms.first().cloned();
+                    }
+                }
+                let line = time + &packet.content;
+                self.add_system_message(line);
+            },
+            PacketType::InfoRequest | PacketType::Command => {
+                eprintln!("This is the packet type sent by the client.")
+            },
+            PacketType::CommandResponse => {
+                for line in packet.content.lines() {
+                    self.add_message(line.to_string());
+                }
+            },
+            PacketType::Connection => {
+                self.add_message(format!("Connected clients: {}", packet.content));
+            },
+            PacketType::UserList => {
+                self.users = packet.users.unwrap_or_default();
+            },
+            PacketType::History => {
+                // 履歴は表示のみ行い、ルームの参加状態などには反映しない
+                let room = packet.room.unwrap_or_default();
+                self.add_message(format!("--- recent messages in #{} ---", room));
+                for past in packet.history.unwrap_or_default() {
+                    match past.packet_type {
+                        // 編集・削除済みのメッセージは編集後の状態で届く
+                        PacketType::Message | PacketType::Edit | PacketType::Delete => self.add_chat_message(past),
+                        _ => {
+                            let line = self.time_prefix(&past) + &past.content;
+                            self.add_system_message(line);
+                        },
+                    }
+                }
+                self.add_message("--- end of recent messages ---".to_string());
+            },
+            PacketType::Error => {
+                self.add_message(format!("Error: {}", packet.content));
+            },
+        }
+    }
+
+    /// チャットメッセージを表示用に整形（表示中以外のルームはルーム名を付ける）
+    fn format_message(&self, packet: &Packet) -> Option<String> {
+        let time = self.time_prefix(packet);
+        let id = match packet.id {
+            Some(id) if self.show_message_ids => format!("[{}] ", id),
+            _ => String::new(),
+        };
+        let room = match packet.room {
+            Some(ref room) if self.current_room.as_ref() != Some(room) => format!("[#{}] ", room),
+            _ => String::new(),
+        };
+        // サーバーからのお知らせ以外は送信者が必要
+        let speaker = match packet.kind {
+            None => format!("{} : ", packet.nickname.as_ref()?),
+            Some(MessageKind::Action) => format!("* {} ", packet.nickname.as_ref()?),
+            Some(MessageKind::Notice) => format!("-{}- ", packet.nickname.as_ref()?),
+            Some(MessageKind::System) => "*** ".to_string(),
+        };
+        let prefix = format!("{}{}{}{}", time, id, room, speaker);
+        let content = match packet.packet_type {
+            PacketType::Edit => format!("{} (edited)", packet.content),
+            PacketType::Delete => "(message deleted)".to_string(),
+            _ if packet.kind == Some(MessageKind::System) => format!("{} ***", packet.content),
+            _ => packet.content.clone(),
+        };
+        // 返信は1行目に返信先の引用を表示する
+        let content = match packet.reply_to {
+            Some(parent) => format!("{}\n{}", self.quote(parent), content),
+            None => content,
+        };
+        // リアクションは本文の下に絵文字ごとの人数を表示する
+        let content = match packet.reactions {
+            Some(ref reactions) if !reactions.is_empty() && packet.packet_type != PacketType::Delete => {
+                let summary: Vec<String> = reactions.iter().map(|r| format!("{} {}", r.emoji, r.users.len())).collect();
+                format!("{}\n{}", content, summary.join("  "))
+            },
+            _ => content,
+        };
+        // 複数行のメッセージは2行目以降を字下げしてまとまりとして表示する
+        Some(text::indent_continuation(&prefix, &content))
+    }
+
+    /// 編集・削除・リアクションを対象のメッセージの行に反映する（表示していないメッセージなら無視）
+    fn update_message(&mut self, message_id: Option<u64>, update: impl FnOnce(&mut Packet)) {
+        let Some(index) = self.chat_lines.iter().position(|line| {
+            line.message.as_ref().is_some_and(|m| m.id.is_some() && m.id == message_id && m.packet_type != PacketType::Delete)
+        }) else {
+            return;
+        };
+
+        let Some(mut message) = self.chat_lines[index].message.take() else {
+            return;
+        };
+        update(&mut message);
+        self.chat_lines[index].text = self.format_message(&message).unwrap_or_default();
+        self.chat_lines[index].message = Some(message);
+        // 折り返し後の行数が変わってもスクロール位置が範囲外にならないようにする
+        self.scroll_offset = self.scroll_offset.min(self.max_scroll_offset());
+    }
+
+    /// 表示中のルームで入力中のユーザーの表示（いなければNone）
+    pub fn typing_status(&self) -> Option<String> {
+        let names: Vec<&str> = self
+            .typing
+            .iter()
+            .filter(|(_, room, at)| self.current_room.as_ref() == Some(room) && at.elapsed() < TYPING_TIMEOUT)
+            .map(|(nickname, _, _)| nickname.as_str())
+            .collect();
+        match names.as_slice() {
+            [] => None,
+            [name] => Some(format!("{} is typing…", name)),
+            [first, second] => Some(format!("{} and {} are typing…", first, second)),
+            _ => Some(format!("{} people are typing…", names.len())),
+        }
+    }
+
+    /// 返信先のメッセージの引用（1行目を切り詰めて表示）
+    fn quote(&self, message_id: u64) -> String {
+        match self.find_message(message_id) {
+            Some(parent) if parent.packet_type != PacketType::Delete => {
+                let nickname = parent.nickname.as_deref().unwrap_or_default();
+                let first_line = parent.content.lines().next().unwrap_or_default();
+                let mut snippet = text::truncate_to_width(first_line, QUOTE_MAX_WIDTH);
+                if snippet.len() < parent.content.len() {
+                    snippet.push('…');
+                }
+                match parent.kind {
+                    Some(MessageKind::Action) => format!("> * {} {}", nickname, snippet),
+                    Some(MessageKind::System) => format!("> *** {}", snippet),
+                    _ => format!("> {}: {}", nickname, snippet),
+                }
+            },
+            Some(_) => "> (message deleted)".to_string(),
+            None => format!("> (message {})", message_id),
+        }
+    }
+
+    /// 表示中のメッセージをIDで検索
+    pub fn find_message(&self, message_id: u64) -> Option<&Packet> {
+        self.chat_lines
+            .iter()
+            .rev()
+            .filter_map(|line| line.message.as_ref())
+            .find(|m| m.id == Some(message_id))
+    }
+
+    /// 選択中のメッセージの行の位置
+    pub fn selected_index(&self) -> Option<usize> {
+        let id = self.selected_message?;
+        self.chat_lines
+            .iter()
+            .position(|line| line.message.as_ref().is_some_and(|m| m.id == Some(id)))
+    }
+
+    /// 1つ前（古い方）のメッセージを選択（Alt-Up）。未選択なら表示中の最新のメッセージから選ぶ
+    pub fn select_prev_message(&mut self) {
+        let end = self
+            .selected_index()
+            .unwrap_or(self.chat_lines.len() - self.scroll_offset.min(self.chat_lines.len()));
+        if let Some(index) = (0..end).rev().find(|&i| self.is_selectable(i)) {
+            self.select_line(index);
+        }
+    }
+
+    /// 1つ後（新しい方）のメッセージを選択（Alt-Down）。最新を越えると選択を解除する
+    pub fn select_next_message(&mut self) {
+        let Some(start) = self.selected_index() else {
+            return;
+        };
+        match (start + 1..self.chat_lines.len()).find(|&i| self.is_selectable(i)) {
+            Some(index) => self.select_line(index),
+            None => self.clear_selection(),
+        }
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selected_message = None;
+    }
+
+    /// 選択できる行か（削除されていないメッセージ。送信者のいないお知らせは除く）
+    fn is_selectable(&self, index: usize) -> bool {
+        self.chat_lines[index]
+            .message
+            .as_ref()
+            .is_some_and(|m| m.id.is_some() && m.packet_type != PacketType::Delete && m.kind != Some(MessageKind::System))
+    }
+
+    /// 行を選択し、表示領域の外にあればその行が最下行になるようスクロールする
+    fn select_line(&mut self, index: usize) {
+        self.selected_message = self.chat_lines[index].message.as_ref().and_then(|m| m.id);
+        let visible_end = self.chat_lines.len() - self.scroll_offset.min(self.chat_lines.len());
+        if index >= visible_end || index < self.first_visible_line() {
+            self.scroll_offset = (self.chat_lines.len() - (index + 1)).min(self.max_scroll_offset());
+        }
+    }
+
+    /// 全体が表示されている最も古い行の位置
+    fn first_visible_line(&self) -> usize {
+        let height = self.chat_height() as usize;
+        let width = self.chat_width() as usize;
+        let visible_end = self.chat_lines.len() - self.scroll_offset.min(self.chat_lines.len());
+        let mut rows = 0;
+        for i in (0..visible_end).rev() {
+            rows += text::wrap_text(self.chat_lines[i].text.trim_end(), width).len();
+            if rows >= height {
+                return if rows == height { i } else { i + 1 };
+            }
+        }
+        0
+    }
+
+    /// 自分が送信した最新のメッセージのID（/edit last・/delete last）
+    pub fn last_own_message_id(&self) -> Option<u64> {
+        self.chat_lines
+            .iter()
+            .rev()
+            .filter_map(|line| line.message.as_ref())
+            .filter_map(|m| m.id.filter(|_| m.packet_type != PacketType::Delete))
+            .find(|id| self.own_messages.contains(id))
+    }
+
+    /// メッセージIDの表示を切り替える
+    pub fn toggle_message_ids(&mut self) {
+        self.show_message_ids = !self.show_message_ids;
+        for i in 0..self.chat_lines.len() {
+            if let Some(text) = self.chat_lines[i].message.as_ref().and_then(|m| self.format_message(m)) {
+                self.chat_lines[i].text = text;
+            }
+        }
+        self.scroll_offset = self.scroll_offset.min(self.max_scroll_offset());
+    }
+
+    /// ルームの通し番号を確認し、欠落があれば通知する（受信済みの番号ならfalse）
+    fn check_sequence(&mut self, packet: &Packet) -> bool {
+        let (Some(room), Some(seq)) = (packet.room.as_ref(), packet.seq) else {
+            return true;
+        };
+        match self.last_seq.get(room) {
+            Some(&last) if seq <= last => return false,
+            Some(&last) if seq > last + 1 => {
+                self.add_message(format!("--- {} messages missed in #{} ---", seq - last - 1, room));
+            },
+            _ => {},
+        }
+        self.last_seq.insert(room.clone(), seq);
+        true
+    }
+
+    /// パケットの時刻を表示する接頭辞（時刻がない・表示しない設定なら空）
+    fn time_prefix(&self, packet: &Packet) -> String {
+        match packet.timestamp {
+            Some(timestamp) if !self.time_format.is_empty() => {
+                format!("{} ", format_timestamp(timestamp, &self.time_format))
+            },
+            _ => String::new(),
+        }
+    }
+
+    /// 自分のニックネームか
+    fn is_own(&self, nickname: &Option<String>) -> bool {
+        nickname.is_some() && *nickname == self.nickname
+    }
+
+    /// 参加中の次のルームへ表示を切り替え
+    pub fn next_room(&mut self) {
+        if self.rooms.is_empty() {
+            return;
+        }
+        let index = self
+            .current_room
+            .as_ref()
+            .and_then(|current| self.rooms.iter().position(|r| r == current))
+            .map_or(0, |i| (i + 1) % self.rooms.len());
+        self.current_room = Some(self.rooms[index].clone());
+        self.add_system_message(format!("*** switched to #{} ***", self.rooms[index]));
+    }
+
+    /// ユーザー一覧サイドバーの表示切り替え
+    pub fn toggle_user_list(&mut self) {
+        self.show_user_list = !self.show_user_list;
+        self.scroll_offset = self.scroll_offset.min(self.max_scroll_offset());
+    }
+
+    /// サイドバーの幅（非表示なら0）
+    pub fn sidebar_width(&self) -> u16 {
+        if self.show_user_list {
+            (self.cols / 3).min(SIDEBAR_MAX_WIDTH)
+        } else {
+            0
         }
     }
 
     pub fn update_size(&mut self, cols: u16, rows: u16) {
         self.cols = cols;
         self.rows = rows;
+        self.scroll_offset = self.scroll_offset.min(self.max_scroll_offset());
     }
 
     pub fn clear_chat(&mut self) {
         self.chat_lines.clear();
+        self.selected_message = None;
+        self.scroll_offset = 0;
     }
 
     pub fn insert_char(&mut self, c: char) {
         self.input.insert(self.cursor, c);
-        self.cursor += 1;
+        self.cursor += c.len_utf8();
+    }
+
+    /// 文字列をまとめて挿入（貼り付け）
+    pub fn insert_str(&mut self, text: &str) {
+        self.input.insert_str(self.cursor, text);
+        self.cursor += text.len();
     }
 
     pub fn backspace(&mut self) {
         if self.cursor > 0 {
-            self.cursor -= 1;
-            self.input.remove(self.cursor);
+            let start = text::prev_grapheme_boundary(&self.input, self.cursor);
+            self.input.replace_range(start..self.cursor, "");
+            self.cursor = start;
         }
     }
 
     pub fn move_cursor_left(&mut self) {
-        if self.cursor > 0 {
-            self.cursor -= 1;
-        }
+        self.cursor = text::prev_grapheme_boundary(&self.input, self.cursor);
     }
 
     pub fn move_cursor_right(&mut self) {
-        if self.cursor < self.input.len() {
-            self.cursor += 1;
+        self.cursor = text::next_grapheme_boundary(&self.input, self.cursor);
+    }
+
+    /// カーソル位置の文字を削除（Delete）
+    pub fn delete(&mut self) {
+        let end = text::next_grapheme_boundary(&self.input, self.cursor);
+        self.input.replace_range(self.cursor..end, "");
+    }
+
+    /// カーソルのある行の先頭
+    fn line_start(&self) -> usize {
+        self.input[..self.cursor].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    /// カーソルのある行の末尾
+    fn line_end(&self) -> usize {
+        self.input[self.cursor..].find('\n').map_or(self.input.len(), |i| self.cursor + i)
+    }
+
+    pub fn move_cursor_home(&mut self) {
+        self.cursor = self.line_start();
+    }
+
+    pub fn move_cursor_end(&mut self) {
+        self.cursor = self.line_end();
+    }
+
+    /// 上の行の同じ表示位置へ移動（先頭行なら移動せずfalse）
+    pub fn move_cursor_up(&mut self) -> bool {
+        let start = self.line_start();
+        if start == 0 {
+            return false;
+        }
+        let column = text::display_width(&self.input[start..self.cursor]);
+        let prev_start = self.input[..start - 1].rfind('\n').map_or(0, |i| i + 1);
+        self.cursor = prev_start + text::offset_at_width(&self.input[prev_start..start - 1], column);
+        true
+    }
+
+    /// 下の行の同じ表示位置へ移動（最終行なら移動せずfalse）
+    pub fn move_cursor_down(&mut self) -> bool {
+        let end = self.line_end();
+        if end == self.input.len() {
+            return false;
+        }
+        let column = text::display_width(&self.input[self.line_start()..self.cursor]);
+        let next_start = end + 1;
+        let next_end = self.input[next_start..].find('\n').map_or(self.input.len(), |i| next_start + i);
+        self.cursor = next_start + text::offset_at_width(&self.input[next_start..next_end], column);
+        true
+    }
+
+    /// カーソル位置で改行（Alt-Enter・Shift-Enter）
+    pub fn insert_newline(&mut self) {
+        self.insert_char('\n');
+    }
+
+    pub fn move_word_left(&mut self) {
+        self.cursor = text::prev_word_start(&self.input, self.cursor);
+    }
+
+    pub fn move_word_right(&mut self) {
+        self.cursor = text::next_word_end(&self.input, self.cursor);
+    }
+
+    /// カーソル前の単語を削除（Ctrl-W）
+    pub fn kill_word_before(&mut self) {
+        let start = text::prev_whitespace_word_start(&self.input, self.cursor);
+        self.kill_range(start, self.cursor);
+    }
+
+    /// 行頭からカーソルまでを削除、行頭なら直前の改行を削除（Ctrl-U）
+    pub fn kill_to_start(&mut self) {
+        let start = match self.line_start() {
+            start if start == self.cursor => start.saturating_sub(1),
+            start => start,
+        };
+        self.kill_range(start, self.cursor);
+    }
+
+    /// カーソルから行末までを削除、行末なら改行を削除（Ctrl-K）
+    pub fn kill_to_end(&mut self) {
+        let end = match self.line_end() {
+            end if end == self.cursor && end < self.input.len() => end + 1,
+            end => end,
+        };
+        self.kill_range(self.cursor, end);
+    }
+
+    /// 最後に削除した文字列をカーソル位置に挿入（Ctrl-Y）
+    pub fn yank(&mut self) {
+        self.input.insert_str(self.cursor, &self.yank_buffer);
+        self.cursor += self.yank_buffer.len();
+    }
+
+    fn kill_range(&mut self, start: usize, end: usize) {
+        if start < end {
+            self.yank_buffer = self.input[start..end].to_string();
+            self.input.replace_range(start..end, "");
+            self.cursor = start;
+        }
+    }
+
+    /// 入力内容を置き換え、カーソルを末尾へ移動
+    pub fn set_input(&mut self, input: String) {
+        self.cursor = input.len();
+        self.input = input;
+    }
+
+    /// 1つ前の入力履歴を呼び出す（Up）
+    pub fn history_prev(&mut self) {
+        // 複数行の入力中は先頭行に達するまで行を移動する
+        if self.move_cursor_up() {
+            return;
+        }
+        if let Some(entry) = self.input_history.older(&self.input).map(str::to_string) {
+            self.set_input(entry);
+        }
+    }
+
+    /// 1つ後の入力履歴を呼び出す（Down）
+    pub fn history_next(&mut self) {
+        if self.move_cursor_down() {
+            return;
+        }
+        if let Some(entry) = self.input_history.newer().map(str::to_string) {
+            self.set_input(entry);
         }
     }
 
+    /// 履歴の逆方向検索を開始、検索中ならさらに古い一致を探す（Ctrl-R）
+    pub fn search_history(&mut self) {
+        let Some(search) = self.history_search.as_mut() else {
+            self.history_search = Some(HistorySearch::default());
+            return;
+        };
+        if let Some(position) = search.position {
+            if let Some(older) = self.input_history.search(&search.query, Some(position)) {
+                search.position = Some(older);
+            }
+        }
+    }
+
+    /// 検索語に1文字追加
+    pub fn search_insert_char(&mut self, c: char) {
+        if let Some(search) = self.history_search.as_mut() {
+            search.query.push(c);
+            search.position = self.input_history.search(&search.query, None);
+        }
+    }
+
+    /// 検索語の末尾を1文字削除
+    pub fn search_backspace(&mut self) {
+        if let Some(search) = self.history_search.as_mut() {
+            search.query.pop();
+            search.position = self.input_history.search(&search.query, None);
+        }
+    }
+
+    /// 検索で一致した履歴を入力欄に取り込んで検索を終了
+    pub fn accept_search(&mut self) {
+        let entry = self
+            .history_search
+            .take()
+            .and_then(|search| search.position)
+            .and_then(|position| self.input_history.get(position))
+            .map(str::to_string);
+        if let Some(entry) = entry {
+            self.set_input(entry);
+        }
+    }
+
+    /// 入力欄を変更せずに検索を終了
+    pub fn cancel_search(&mut self) {
+        self.history_search = None;
+    }
+
+    /// カーソル位置の単語をニックネーム・コマンド名で補完する（続けて押すと次の候補）
+    pub fn complete(&mut self, forward: bool) {
+        if let Some(completion) = self.completion.as_mut() {
+            completion.cycle(forward);
+        } else {
+            let nicknames: Vec<String> = self
+                .users
+                .iter()
+                .filter(|u| self.nickname.as_ref() != Some(&u.nickname))
+                .map(|u| u.nickname.clone())
+                .collect();
+            let Some(mut completion) = Completion::new(&self.input, self.cursor, &nicknames) else {
+                return;
+            };
+            if !forward {
+                completion.cycle(false);
+            }
+            self.completion = Some(completion);
+        }
+
+        let Some(completion) = self.completion.as_ref() else {
+            return;
+        };
+        self.input.replace_range(completion.start..self.cursor, completion.current());
+        self.cursor = completion.start + completion.current().len();
+    }
+
+    /// 送信する入力を取り出して入力欄を空にする
     pub fn take_input(&mut self) -> String {
         let input = self.input.clone();
         self.input.clear();
         self.cursor = 0;
+        self.large_paste = false;
         input
     }
 }
@@ -82,6 +888,8 @@ impl AppState {
 /// ターミナルUI管理
 pub struct ClientUI {
     stdout: io::Stdout,
+    /// Shift-Enterを判別できるようキーボード拡張を有効にしたか
+    keyboard_enhanced: bool,
 }
 
 impl ClientUI {
@@ -89,44 +897,187 @@ impl ClientUI {
         let mut stdout = io::stdout();
         terminal::enable_raw_mode()?;
         stdout.execute(EnterAlternateScreen)?;
+        stdout.execute(EnableMouseCapture)?;
+        stdout.execute(EnableBracketedPaste)?;
         stdout.execute(Clear(ClearType::All))?;
-        
-        Ok(Self { stdout })
+
+        let keyboard_enhanced = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if keyboard_enhanced {
+            stdout.execute(PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES))?;
+        }
+
+        Ok(Self { stdout, keyboard_enhanced })
     }
 
     pub fn render(&mut self, state: &AppState) -> Result<()> {
         // 画面全体を再描画
         self.stdout.queue(Clear(ClearType::All))?;
 
-        // チャット表示
-        let chat_height = state.rows.saturating_sub(1);
-        for (i, line) in state.chat_lines.iter().rev().enumerate() {
-            if i as u16 >= chat_height {
+        // チャット表示（スクロール位置より下の行は表示しない）
+        let chat_height = state.chat_height();
+        let chat_width = state.chat_width() as usize;
+        let sidebar_width = state.sidebar_width();
+        let visible_end = state.chat_lines.len() - state.scroll_offset.min(state.chat_lines.len());
+
+        // 下から順に、折り返した行で表示領域を埋める（選択中のメッセージは反転表示）
+        let selected = state.selected_index();
+        let mut screen_rows: Vec<(String, bool, Option<Attribute>)> = Vec::new();
+        for (index, line) in state.chat_lines[..visible_end].iter().enumerate().rev() {
+            let wrapped = text::wrap_text(line.text.trim_end(), chat_width);
+            let attribute = line.attribute();
+            screen_rows.extend(wrapped.into_iter().rev().map(|row| (row, selected == Some(index), attribute)));
+            if screen_rows.len() >= chat_height as usize {
                 break;
             }
+        }
+        for (i, (row, highlighted, attribute)) in screen_rows.iter().take(chat_height as usize).enumerate() {
             let y = chat_height - 1 - i as u16;
+            self.stdout.queue(MoveTo(0, y))?;
+            if *highlighted {
+                self.stdout.queue(SetAttribute(Attribute::Reverse))?;
+            }
+            if let Some(attribute) = attribute {
+                self.stdout.queue(SetAttribute(*attribute))?;
+            }
+            self.stdout.queue(Print(row))?.queue(SetAttribute(Attribute::Reset))?;
+        }
+
+        // スクロール中は最下行に未読行数を表示
+        if state.scroll_offset > 0 && chat_height > 0 {
+            let indicator = format!("-- more below ({} lines) --", state.rows_below());
             self.stdout
-                .queue(MoveTo(0, y))?
-                .queue(Print(line.trim_end()))?;
+                .queue(MoveTo(0, chat_height - 1))?
+                .queue(Clear(ClearType::CurrentLine))?
+                .queue(SetAttribute(Attribute::Reverse))?
+                .queue(Print(indicator))?
+                .queue(SetAttribute(Attribute::Reset))?;
         }
 
-        // 入力行描画
-        let prompt = "> ";
-        self.stdout
-            .queue(MoveTo(0, state.rows - 1))?
-            .queue(Print(format!("{prompt}{}", state.input)))?;
+        // ユーザー一覧サイドバー
+        if sidebar_width > 0 {
+            self.render_user_list(state, sidebar_width, chat_height)?;
+        }
+
+        // ステータス行（入力中のユーザー）
+        if let Some(status) = state.typing_status() {
+            let status = text::truncate_to_width(&status, state.cols as usize);
+            self.stdout
+                .queue(MoveTo(0, chat_height))?
+                .queue(SetAttribute(Attribute::Dim))?
+                .queue(Print(status))?
+                .queue(SetAttribute(Attribute::Reset))?;
+        }
 
-        // カーソル位置調整
-        let cursor_x = prompt.len() as u16 + state.cursor as u16;
-        self.stdout.queue(MoveTo(cursor_x, state.rows - 1))?;
+        // 入力行描画（端末幅を超える場合はカーソルが見える位置まで横スクロール）
+        let (prompt, input, cursor) = match state.history_search {
+            // 履歴検索中は一致した履歴を表示し、一致箇所にカーソルを置く
+            Some(ref search) => {
+                let entry = search.position.and_then(|p| state.input_history.get(p)).unwrap_or_default();
+                let cursor = entry.find(search.query.as_str()).unwrap_or(0);
+                (format!("(reverse-i-search)`{}': ", search.query), entry, cursor)
+            },
+            None => {
+                let room = match state.current_room {
+                    Some(ref room) => format!("[#{room}] "),
+                    None => String::new(),
+                };
+                // メッセージを選択中は返信先を表示する
+                let prompt = match state.selected_message.and_then(|id| state.find_message(id)) {
+                    Some(parent) => format!("{}reply to {} > ", room, parent.nickname.as_deref().unwrap_or_default()),
+                    None => format!("{}> ", room),
+                };
+                (prompt, state.input.as_str(), state.cursor)
+            },
+        };
+        let prompt_width = text::display_width(&prompt);
+        let input_width = (state.cols as usize).saturating_sub(prompt_width);
+        let input_height = state.input_height() as usize;
+        let input_top = state.rows.saturating_sub(input_height as u16);
+
+        // カーソルのある行が入力欄に収まるよう縦方向にもスクロール
+        let cursor_line = input[..cursor].matches('\n').count();
+        let first_line = (cursor_line + 1).saturating_sub(input_height);
+        let mut line_start = 0;
+        let mut cursor_pos = (0, 0);
+        for (i, line) in input.split('\n').enumerate() {
+            let line_end = line_start + line.len();
+            if (first_line..first_line + input_height).contains(&i) {
+                // 2行目以降はプロンプトの幅だけ字下げ
+                let label = if i == 0 { prompt.clone() } else { " ".repeat(prompt_width) };
+                let y = input_top + (i - first_line) as u16;
+                let start = if i == cursor_line {
+                    let start = input_scroll_start(line, cursor - line_start, input_width);
+                    cursor_pos = (prompt_width + text::display_width(&line[start..cursor - line_start]), y);
+                    start
+                } else {
+                    0
+                };
+                let visible = text::truncate_to_width(&line[start..], input_width);
+                self.stdout
+                    .queue(MoveTo(0, y))?
+                    .queue(Print(format!("{label}{visible}")))?;
+            }
+            line_start = line_end + 1;
+        }
+
+        // カーソル位置調整（表示幅で計算し、IMEの変換窓もこの位置に出る）
+        self.stdout.queue(MoveTo(cursor_pos.0 as u16, cursor_pos.1))?;
 
         self.stdout.flush()?;
         Ok(())
     }
 
+    fn render_user_list(&mut self, state: &AppState, width: u16, height: u16) -> Result<()> {
+        let x = state.cols.saturating_sub(width);
+        let inner = width.saturating_sub(2) as usize;
+        let header = format!("Users ({})", state.users.len());
+        let lines = std::iter::once(header)
+            .chain(state.users.iter().map(|u| match (u.presence, u.status.as_deref()) {
+                (Presence::Online, None) => u.nickname.clone(),
+                (Presence::Online, Some(status)) => format!("{} - {}", u.nickname, status),
+                (presence, None) => format!("{} ({})", u.nickname, presence.as_str()),
+                // 長いステータスは下で幅に合わせて切り詰める
+                (presence, Some(status)) => format!("{} ({}: {})", u.nickname, presence.as_str(), status),
+            }));
+
+        for y in 0..height {
+            self.stdout.queue(MoveTo(x, y))?.queue(Print("│"))?;
+        }
+        for (y, line) in lines.take(height as usize).enumerate() {
+            let line = text::truncate_to_width(&line, inner);
+            self.stdout
+                .queue(MoveTo(x + 2, y as u16))?
+                .queue(Print(line))?;
+        }
+        Ok(())
+    }
+
     pub fn cleanup(&mut self) -> Result<()> {
+        if self.keyboard_enhanced {
+            self.stdout.execute(PopKeyboardEnhancementFlags)?;
+        }
+        self.stdout.execute(DisableBracketedPaste)?;
+        self.stdout.execute(DisableMouseCapture)?;
         self.stdout.execute(LeaveAlternateScreen)?;
         terminal::disable_raw_mode()?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// 入力行の表示開始位置（カーソルが表示幅に収まる最小のバイト位置）
+fn input_scroll_start(input: &str, cursor: usize, width: usize) -> usize {
+    // カーソル自体の1桁分を残す
+    let limit = width.saturating_sub(1);
+    let mut start = 0;
+    while start < cursor && text::display_width(&input[start..cursor]) > limit {
+        start = text::next_grapheme_boundary(input, start);
+    }
+    start
+}
+/// 時刻の表示形式を環境変数から読み込む（不正な形式なら既定の形式）
+fn load_time_format() -> String {
+    match env::var(TIME_FORMAT_ENV) {
+        Ok(format) if !StrftimeItems::new(&format).any(|item| item == Item::Error) => format,
+        _ => DEFAULT_TIME_FORMAT.to_string(),
+    }
+}
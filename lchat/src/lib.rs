@@ -1,25 +1,222 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{Local, TimeZone};
 use serde::{Deserialize, Serialize};
 
 pub mod server;
 pub mod client;
 
+/// 接続直後に参加するルーム
+pub const DEFAULT_ROOM: &str = "lobby";
+
+/// ニックネームの最大文字数
+pub const MAX_NICKNAME_LEN: usize = 16;
+
+/// 在席状況の説明文の最大文字数
+pub const MAX_STATUS_LEN: usize = 64;
+
+/// リアクションの最大文字数
+pub const MAX_REACTION_LEN: usize = 16;
+
+/// 絵文字に置き換えるショートコード
+const REACTION_SHORTCODES: &[(&str, &str)] = &[
+    (":+1:", "👍"),
+    (":thumbsup:", "👍"),
+    (":-1:", "👎"),
+    (":thumbsdown:", "👎"),
+    (":heart:", "❤️"),
+    (":joy:", "😂"),
+    (":smile:", "😄"),
+    (":tada:", "🎉"),
+    (":eyes:", "👀"),
+    (":fire:", "🔥"),
+    (":pray:", "🙏"),
+];
+
+/// リアクションを検証し、既知のショートコードを絵文字に揃える
+pub fn normalize_reaction(reaction: &str) -> Result<String, String> {
+    let reaction = reaction.trim();
+    if reaction.is_empty() || reaction.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err("Reaction must be a single emoji or :shortcode:".to_string());
+    }
+    if reaction.chars().count() > MAX_REACTION_LEN {
+        return Err(format!("Reaction must be at most {} characters", MAX_REACTION_LEN));
+    }
+    let emoji = REACTION_SHORTCODES
+        .iter()
+        .find(|(code, _)| *code == reaction)
+        .map_or(reaction, |(_, emoji)| emoji);
+    Ok(emoji.to_string())
+}
+
+/// ニックネームの検証（文字数と使用可能な文字）
+pub fn validate_nickname(nickname: &str) -> Result<(), String> {
+    let len = nickname.chars().count();
+    if len == 0 {
+        return Err("Nickname is empty".to_string());
+    }
+    if len > MAX_NICKNAME_LEN {
+        return Err(format!("Nickname must be at most {} characters", MAX_NICKNAME_LEN));
+    }
+    if !nickname.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.') {
+        return Err("Nickname may only contain letters, digits, '-', '_' or '.'".to_string());
+    }
+    Ok(())
+}
+
+/// 本文を中継できる形に整える
+///
+/// 改行コードをLFに揃え、タブを空白に置き換え、端末を操作できてしまう制御文字を取り除く
+pub fn sanitize_content(content: &str) -> String {
+    content
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .replace('\t', "    ")
+        .chars()
+        .filter(|&c| c == '\n' || !c.is_control())
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+/// ログに残すコマンド（`/oper` の引数のパスワードは伏せる）
+///
+/// サーバーのコマンドと同じく、コマンド名は大文字小文字を区別せずに判定する
+fn mask_command(content: &str) -> String {
+    let input = content.trim().trim_start_matches('/');
+    let (name, args) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+    if name.eq_ignore_ascii_case("oper") && !args.trim().is_empty() {
+        format!("{} ****", name)
+    } else {
+        input.to_string()
+    }
+}
+
+/// 現在時刻（UNIX時間・秒）
+pub fn current_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// UNIX時間をローカル時刻として`format`（strftime形式）で整形する
+pub fn format_timestamp(timestamp: u64, format: &str) -> String {
+    i64::try_from(timestamp)
+        .ok()
+        .and_then(|secs| Local.timestamp_opt(secs, 0).single())
+        .map(|time| time.format(format).to_string())
+        .unwrap_or_default()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum PacketType {
     NicknameRequest,
     NicknameResponse,
+    NicknameChange,
     Message,
+    PrivateMessage,
+    Edit,
+    Delete,
+    Reaction,
+    Typing,
+    Presence,
     Join,
     Leave,
     InfoRequest,
+    Command,
+    CommandResponse,
     Connection,
+    UserList,
+    History,
     Error,
 }
 
+/// ユーザーの在席状況
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum Presence {
+    #[default]
+    Online,
+    Away,
+    Busy,
+}
+
+impl Presence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Presence::Online => "online",
+            Presence::Away => "away",
+            Presence::Busy => "busy",
+        }
+    }
+}
+
+/// メッセージの種類（通常の発言はNone）
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum MessageKind {
+    /// 動作の描写（`/me waves` → `* alice waves`）
+    Action,
+    /// 返信を求めない通知
+    Notice,
+    /// サーバーからのお知らせ（クライアントからは送信できない）
+    System,
+}
+
+/// ユーザー一覧の各エントリ
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UserInfo {
+    pub nickname: String,
+    #[serde(default)]
+    pub presence: Presence,
+    /// 在席状況の説明（例: 昼休み）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+}
+
+/// メッセージへのリアクションの集計（絵文字ごと）
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReactionInfo {
+    pub emoji: String,
+    /// リアクションしたユーザー（古い順）
+    pub users: Vec<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Packet {
     pub packet_type: PacketType,
     pub content: String,
     pub nickname: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub room: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub users: Option<Vec<UserInfo>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<Vec<Packet>>,
+    /// サーバーが受信・中継した時刻（UNIX時間・秒）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    /// サーバーが中継時に付与する一意なID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    /// ルーム内で単調増加する通し番号（欠落の検出に使う）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    /// 編集・削除の対象のメッセージID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<u64>,
+    /// 返信先のメッセージID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<u64>,
+    /// メッセージへのリアクションの集計
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reactions: Option<Vec<ReactionInfo>>,
+    /// 在席状況（説明はcontent）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence: Option<Presence>,
+    /// メッセージの種類
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<MessageKind>,
 }
 
 impl Packet {
@@ -28,9 +225,26 @@ impl Packet {
             packet_type,
             content,
             nickname,
+            room: None,
+            target: None,
+            users: None,
+            history: None,
+            timestamp: None,
+            id: None,
+            seq: None,
+            message_id: None,
+            reply_to: None,
+            reactions: None,
+            presence: None,
+            kind: None,
         }
     }
 
+    /// サーバーの現在時刻を付与する
+    pub fn stamped(self) -> Self {
+        Self { timestamp: Some(current_timestamp()), ..self }
+    }
+
     pub fn nickname_request() -> Self {
         Self::new(PacketType::NicknameRequest, "Please enter your nickname: ".to_string(), None)
     }
@@ -39,24 +253,200 @@ impl Packet {
         Self::new(PacketType::NicknameResponse, nickname, None)
     }
 
-    pub fn message(content: String, nickname: String) -> Self {
-        Self::new(PacketType::Message, content, Some(nickname))
+    /// ニックネーム変更要求（クライアント → サーバー）
+    pub fn nickname_change_request(nickname: String) -> Self {
+        Self::new(PacketType::NicknameChange, nickname, None)
+    }
+
+    /// ニックネーム変更の通知（nicknameが旧名、targetが新名）
+    pub fn nickname_change(old: String, new: String) -> Self {
+        Self {
+            target: Some(new.clone()),
+            ..Self::new(PacketType::NicknameChange, format!("*** {} is now known as {} ***", old, new), Some(old))
+        }
+    }
+
+    pub fn message(content: String, nickname: String, room: String) -> Self {
+        Self {
+            room: Some(room),
+            ..Self::new(PacketType::Message, content, Some(nickname))
+        }
+    }
+
+    /// サーバーからのお知らせ（送信者なし）
+    pub fn system(content: String, room: String) -> Self {
+        Self {
+            room: Some(room),
+            kind: Some(MessageKind::System),
+            ..Self::new(PacketType::Message, content, None)
+        }
+    }
+
+    /// 種類付きのメッセージ（`/me` や `/notice`）
+    pub fn message_with_kind(content: String, nickname: String, room: String, kind: MessageKind) -> Self {
+        Self {
+            kind: Some(kind),
+            ..Self::message(content, nickname, room)
+        }
+    }
+
+    /// メッセージへの返信
+    pub fn reply(content: String, nickname: String, room: String, reply_to: u64) -> Self {
+        Self {
+            reply_to: Some(reply_to),
+            ..Self::message(content, nickname, room)
+        }
+    }
+
+    /// 特定のニックネーム宛てのメッセージ
+    pub fn private_message(content: String, nickname: String, target: String) -> Self {
+        Self {
+            target: Some(target),
+            ..Self::new(PacketType::PrivateMessage, content, Some(nickname))
+        }
+    }
+
+    /// 入力中の通知（ログにも履歴にも残さない）
+    pub fn typing(nickname: String, room: String) -> Self {
+        Self {
+            room: Some(room),
+            ..Self::new(PacketType::Typing, String::new(), Some(nickname))
+        }
+    }
+
+    /// 在席状況の変更要求（クライアント → サーバー）
+    pub fn presence_request(presence: Presence, status: String) -> Self {
+        Self {
+            presence: Some(presence),
+            ..Self::new(PacketType::Presence, status, None)
+        }
+    }
+
+    /// 在席状況の変更の通知
+    pub fn presence_change(nickname: String, presence: Presence, status: String) -> Self {
+        Self {
+            presence: Some(presence),
+            ..Self::new(PacketType::Presence, status, Some(nickname))
+        }
+    }
+
+    pub fn join(nickname: String, room: String) -> Self {
+        Self {
+            room: Some(room.clone()),
+            ..Self::new(PacketType::Join, format!("*** {} joined #{} ***", nickname, room), Some(nickname))
+        }
+    }
+
+    pub fn leave(nickname: String, room: String) -> Self {
+        Self {
+            room: Some(room.clone()),
+            ..Self::new(PacketType::Leave, format!("*** {} left #{} ***", nickname, room), Some(nickname))
+        }
     }
 
-    pub fn join(nickname: String) -> Self {
-        Self::new(PacketType::Join, format!("*** {} joined ***", nickname), Some(nickname))
+    /// ルームへの参加要求（クライアント → サーバー）
+    pub fn join_request(room: String) -> Self {
+        Self {
+            room: Some(room),
+            ..Self::new(PacketType::Join, String::new(), None)
+        }
     }
 
-    pub fn leave(nickname: String) -> Self {
-        Self::new(PacketType::Leave, format!("*** {} left ***", nickname), Some(nickname))
+    /// ルームからの退出要求（クライアント → サーバー）
+    pub fn leave_request(room: String) -> Self {
+        Self {
+            room: Some(room),
+            ..Self::new(PacketType::Leave, String::new(), None)
+        }
     }
 
     pub fn info_request(info: String) -> Self {
-        Self::new(PacketType::InfoRequest, format!("{}", info), None)
+        Self::new(PacketType::InfoRequest, info, None)
+    }
+
+    /// サーバー側コマンドの実行要求（roomは実行時に表示中のルーム）
+    pub fn command(command: String, room: Option<String>) -> Self {
+        Self {
+            room,
+            ..Self::new(PacketType::Command, command, None)
+        }
+    }
+
+    /// コマンドの実行結果
+    pub fn command_response(content: String) -> Self {
+        Self::new(PacketType::CommandResponse, content, None)
     }
 
     pub fn connection(num: usize) -> Self {
-        Self::new(PacketType::Connection, format!("{}", num.to_string()), None)
+        Self::new(PacketType::Connection, num.to_string(), None)
+    }
+
+    /// 接続中のユーザー一覧
+    pub fn user_list(users: Vec<UserInfo>) -> Self {
+        Self {
+            users: Some(users),
+            ..Self::new(PacketType::UserList, String::new(), None)
+        }
+    }
+
+    /// 入室時に再送するルームの直近の履歴
+    pub fn history(room: String, packets: Vec<Packet>) -> Self {
+        Self {
+            room: Some(room),
+            history: Some(packets),
+            ..Self::new(PacketType::History, String::new(), None)
+        }
+    }
+
+    /// メッセージの編集要求（クライアント → サーバー）
+    pub fn edit_request(message_id: u64, content: String) -> Self {
+        Self {
+            message_id: Some(message_id),
+            ..Self::new(PacketType::Edit, content, None)
+        }
+    }
+
+    /// メッセージの削除要求（クライアント → サーバー）
+    pub fn delete_request(message_id: u64) -> Self {
+        Self {
+            message_id: Some(message_id),
+            ..Self::new(PacketType::Delete, String::new(), None)
+        }
+    }
+
+    /// メッセージの編集の通知（nicknameは編集した人）
+    pub fn edit(message_id: u64, content: String, nickname: String, room: String) -> Self {
+        Self {
+            room: Some(room),
+            message_id: Some(message_id),
+            ..Self::new(PacketType::Edit, content, Some(nickname))
+        }
+    }
+
+    /// メッセージの削除の通知（nicknameは削除した人）
+    pub fn delete(message_id: u64, nickname: String, room: String) -> Self {
+        Self {
+            room: Some(room),
+            message_id: Some(message_id),
+            ..Self::new(PacketType::Delete, String::new(), Some(nickname))
+        }
+    }
+
+    /// リアクションの追加・取り消し要求（クライアント → サーバー）
+    pub fn reaction_request(message_id: u64, emoji: String) -> Self {
+        Self {
+            message_id: Some(message_id),
+            ..Self::new(PacketType::Reaction, emoji, None)
+        }
+    }
+
+    /// リアクションの通知（nicknameはリアクションした人、reactionsは反映後の集計）
+    pub fn reaction(message_id: u64, emoji: String, nickname: String, room: String) -> Self {
+        Self {
+            room: Some(room),
+            message_id: Some(message_id),
+            ..Self::new(PacketType::Reaction, emoji, Some(nickname))
+        }
     }
 
     pub fn error(content: String) -> Self {
@@ -72,15 +462,79 @@ impl Packet {
     }
 
     pub fn log(&self) {
-        match self.packet_type {
-            PacketType::NicknameRequest => println!("[NicknameRequest] : {}", self.content),
-            PacketType::NicknameResponse => println!("[NicknameResponse] : {}", self.content),
-            PacketType::Message => println!("[Message] {} : {}", self.nickname.as_deref().unwrap_or("Unknown"), self.content),
-            PacketType::Join => println!("[Join] {}", self.content),
-            PacketType::Leave => println!("[Leave] {}", self.content),
-            PacketType::InfoRequest => println!("[InfoRequest] {}", self.content),
-            PacketType::Connection => println!("[Connection] {}", self.content),
-            PacketType::Error => eprintln!("[Error] {}", self.content),
-        }
-    }
-}
\ No newline at end of file
+        let room = self.room.as_deref().unwrap_or(DEFAULT_ROOM);
+        let line = match self.packet_type {
+            PacketType::NicknameRequest => format!("[NicknameRequest] : {}", self.content),
+            PacketType::NicknameResponse => format!("[NicknameResponse] : {}", self.content),
+            PacketType::NicknameChange => format!("[NicknameChange] {} : {}", self.nickname.as_deref().unwrap_or("Unknown"), self.content),
+            PacketType::Message if self.kind.is_some() => format!("[{:?}] #{} {} : {}", self.kind.unwrap_or(MessageKind::Action), room, self.nickname.as_deref().unwrap_or("Server"), self.content.replace('\n', "\\n")),
+            PacketType::Message => format!("[Message] #{} {} : {}", room, self.nickname.as_deref().unwrap_or("Unknown"), self.content.replace('\n', "\\n")),
+            PacketType::PrivateMessage => format!("[PrivateMessage] {} -> {} : {}", self.nickname.as_deref().unwrap_or("Unknown"), self.target.as_deref().unwrap_or("Unknown"), self.content.replace('\n', "\\n")),
+            PacketType::Edit => format!("[Edit] #{} {} : {} -> {}", room, self.nickname.as_deref().unwrap_or("Unknown"), self.message_id.unwrap_or_default(), self.content.replace('\n', "\\n")),
+            PacketType::Delete => format!("[Delete] #{} {} : {}", room, self.nickname.as_deref().unwrap_or("Unknown"), self.message_id.unwrap_or_default()),
+            PacketType::Reaction => format!("[Reaction] #{} {} : {} {}", room, self.nickname.as_deref().unwrap_or("Unknown"), self.message_id.unwrap_or_default(), self.content),
+            PacketType::Typing => format!("[Typing] #{} {}", room, self.nickname.as_deref().unwrap_or("Unknown")),
+            PacketType::Presence => format!("[Presence] {} : {} {}", self.nickname.as_deref().unwrap_or("Unknown"), self.presence.unwrap_or_default().as_str(), self.content),
+            PacketType::Join => format!("[Join] #{} {}", room, self.content),
+            PacketType::Leave => format!("[Leave] #{} {}", room, self.content),
+            PacketType::InfoRequest => format!("[InfoRequest] {}", self.content),
+            PacketType::Command => format!("[Command] {} : /{}", self.nickname.as_deref().unwrap_or("Unknown"), mask_command(&self.content)),
+            PacketType::CommandResponse => format!("[CommandResponse] {}", self.content),
+            PacketType::Connection => format!("[Connection] {}", self.content),
+            PacketType::History => format!("[History] #{} {} messages", room, self.history.as_ref().map_or(0, Vec::len)),
+            PacketType::UserList => format!("[UserList] {} users", self.users.as_ref().map_or(0, Vec::len)),
+            PacketType::Error => format!("[Error] {}", self.content),
+        };
+
+        // 時刻が付与されていれば先頭に表示する
+        let line = match self.timestamp {
+            Some(timestamp) => format!("{} {}", format_timestamp(timestamp, "%Y-%m-%d %H:%M:%S"), line),
+            None => line,
+        };
+        if self.packet_type == PacketType::Error {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nickname_accepts_letters_digits_and_symbols() {
+        assert!(validate_nickname("alice").is_ok());
+        assert!(validate_nickname("bob_2.0-x").is_ok());
+        assert!(validate_nickname("たろう").is_ok());
+    }
+
+    #[test]
+    fn nickname_rejects_empty_long_and_invalid() {
+        assert!(validate_nickname("").is_err());
+        assert!(validate_nickname(&"a".repeat(MAX_NICKNAME_LEN + 1)).is_err());
+        assert!(validate_nickname(&"あ".repeat(MAX_NICKNAME_LEN)).is_ok());
+        assert!(validate_nickname("bad name").is_err());
+        assert!(validate_nickname("bad\u{1b}").is_err());
+    }
+
+    #[test]
+    fn oper_password_is_masked_in_logs() {
+        assert_eq!(mask_command("oper hunter2"), "oper ****");
+        assert_eq!(mask_command("/OPER hunter2 extra"), "OPER ****");
+        assert_eq!(mask_command("who lobby"), "who lobby");
+    }
+
+    #[test]
+    fn sanitize_normalizes_newlines_and_strips_controls() {
+        assert_eq!(sanitize_content("a\r\nb\rc\t\u{1b}[2Jd  \n"), "a\nb\nc    [2Jd");
+    }
+
+    #[test]
+    fn reaction_expands_shortcodes() {
+        assert_eq!(normalize_reaction(" :+1: ").as_deref(), Ok("👍"));
+        assert_eq!(normalize_reaction("🎉").as_deref(), Ok("🎉"));
+        assert!(normalize_reaction("two words").is_err());
+    }
+}
@@ -1,14 +1,30 @@
 use std::{
+    collections::HashSet,
     net::TcpStream,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
 };
 
+use crate::{Presence, UserInfo};
+
 /// クライアントごとの保持情報
 pub struct Client {
     pub stream: Arc<Mutex<TcpStream>>,
+    /// 接続ごとに一意なID（メッセージの所有者の判定に使う）
+    pub session: u64,
     pub name: String,
+    pub rooms: HashSet<String>,
+    pub is_operator: bool,
+    pub presence: Presence,
+    /// 在席状況の説明
+    pub status: Option<String>,
 }
 
+/// 次に割り当てるセッションID
+static NEXT_SESSION: AtomicU64 = AtomicU64::new(1);
+
 /// 共有クライアントリスト
 pub type SharedClients = Arc<Mutex<Vec<Client>>>;
 
@@ -17,20 +33,196 @@ pub fn create_clients_list() -> SharedClients {
     Arc::new(Mutex::new(Vec::new()))
 }
 
-/// クライアントを追加
-pub fn add_client(clients: &SharedClients, stream: Arc<Mutex<TcpStream>>, name: String) {
-    clients.lock().unwrap().push(Client { stream, name });
+/// クライアントを追加（同じニックネームが使用中ならfalse）
+pub fn add_client(clients: &SharedClients, stream: Arc<Mutex<TcpStream>>, name: String) -> bool {
+    let mut list = clients.lock().unwrap();
+    if list.iter().any(|c| is_same_name(&c.name, &name)) {
+        return false;
+    }
+    list.push(Client {
+        stream,
+        session: NEXT_SESSION.fetch_add(1, Ordering::Relaxed),
+        name,
+        rooms: HashSet::new(),
+        is_operator: false,
+        presence: Presence::Online,
+        status: None,
+    });
+    true
 }
 
-/// クライアントを削除
-pub fn remove_client(clients: &SharedClients, stream: &Arc<Mutex<TcpStream>>) {
-    clients
-        .lock()
-        .unwrap()
-        .retain(|c| !Arc::ptr_eq(&c.stream, stream));
+/// クライアントを削除し、削除したクライアントを返す
+pub fn remove_client(clients: &SharedClients, stream: &Arc<Mutex<TcpStream>>) -> Option<Client> {
+    let mut list = clients.lock().unwrap();
+    let index = list.iter().position(|c| Arc::ptr_eq(&c.stream, stream))?;
+    Some(list.remove(index))
 }
 
 /// 接続中のクライアント数を取得
 pub fn get_client_count(clients: &SharedClients) -> usize {
     clients.lock().unwrap().len()
-}
\ No newline at end of file
+}
+
+/// 接続中のユーザー一覧を取得（ニックネーム順）
+pub fn get_user_list(clients: &SharedClients) -> Vec<UserInfo> {
+    let mut users: Vec<UserInfo> = clients
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|c| UserInfo { nickname: c.name.clone(), presence: c.presence, status: c.status.clone() })
+        .collect();
+    users.sort_by_key(|u| u.nickname.to_lowercase());
+    users
+}
+
+/// ルームに参加（新規参加ならtrue）
+pub fn join_room(clients: &SharedClients, stream: &Arc<Mutex<TcpStream>>, room: &str) -> bool {
+    clients
+        .lock()
+        .unwrap()
+        .iter_mut()
+        .find(|c| Arc::ptr_eq(&c.stream, stream))
+        .is_some_and(|c| c.rooms.insert(room.to_string()))
+}
+
+/// ルームから退出（参加していた場合true）
+pub fn leave_room(clients: &SharedClients, stream: &Arc<Mutex<TcpStream>>, room: &str) -> bool {
+    clients
+        .lock()
+        .unwrap()
+        .iter_mut()
+        .find(|c| Arc::ptr_eq(&c.stream, stream))
+        .is_some_and(|c| c.rooms.remove(room))
+}
+
+/// ルームに参加しているか
+pub fn is_in_room(clients: &SharedClients, stream: &Arc<Mutex<TcpStream>>, room: &str) -> bool {
+    clients
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|c| Arc::ptr_eq(&c.stream, stream) && c.rooms.contains(room))
+}
+
+/// ストリームに対応するクライアントの現在のニックネームを取得
+pub fn get_client_name(clients: &SharedClients, stream: &Arc<Mutex<TcpStream>>) -> Option<String> {
+    clients
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|c| Arc::ptr_eq(&c.stream, stream))
+        .map(|c| c.name.clone())
+}
+
+/// クライアントのセッションIDを取得
+pub fn get_session(clients: &SharedClients, stream: &Arc<Mutex<TcpStream>>) -> Option<u64> {
+    clients
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|c| Arc::ptr_eq(&c.stream, stream))
+        .map(|c| c.session)
+}
+
+/// ニックネームを変更し、変更前のニックネームを返す（他のクライアントが使用中ならErr）
+pub fn rename_client(clients: &SharedClients, stream: &Arc<Mutex<TcpStream>>, name: &str) -> Result<String, String> {
+    let mut list = clients.lock().unwrap();
+    if list.iter().any(|c| !Arc::ptr_eq(&c.stream, stream) && is_same_name(&c.name, name)) {
+        return Err(format!("Nickname '{}' is already in use", name));
+    }
+    let client = list
+        .iter_mut()
+        .find(|c| Arc::ptr_eq(&c.stream, stream))
+        .ok_or_else(|| "You are not registered".to_string())?;
+    Ok(std::mem::replace(&mut client.name, name.to_string()))
+}
+
+/// ニックネームからクライアントのストリームと正式なニックネームを取得
+pub fn find_client_by_name(clients: &SharedClients, name: &str) -> Option<(Arc<Mutex<TcpStream>>, String)> {
+    clients
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|c| is_same_name(&c.name, name))
+        .map(|c| (Arc::clone(&c.stream), c.name.clone()))
+}
+
+/// クライアントが参加中のルーム一覧（ルーム名順）
+pub fn get_rooms(clients: &SharedClients, stream: &Arc<Mutex<TcpStream>>) -> Vec<String> {
+    let mut rooms: Vec<String> = clients
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|c| Arc::ptr_eq(&c.stream, stream))
+        .map(|c| c.rooms.iter().cloned().collect())
+        .unwrap_or_default();
+    rooms.sort();
+    rooms
+}
+
+/// ルームの参加者のニックネーム一覧を取得（ニックネーム順）
+pub fn get_room_members(clients: &SharedClients, room: &str) -> Vec<String> {
+    let mut members: Vec<String> = clients
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|c| c.rooms.contains(room))
+        .map(|c| c.name.clone())
+        .collect();
+    members.sort_by_key(|name| name.to_lowercase());
+    members
+}
+
+/// オペレーター権限を付与
+pub fn set_operator(clients: &SharedClients, stream: &Arc<Mutex<TcpStream>>) {
+    if let Some(client) = clients.lock().unwrap().iter_mut().find(|c| Arc::ptr_eq(&c.stream, stream)) {
+        client.is_operator = true;
+    }
+}
+
+/// 在席状況を変更
+pub fn set_presence(clients: &SharedClients, stream: &Arc<Mutex<TcpStream>>, presence: Presence, status: Option<String>) {
+    if let Some(client) = clients.lock().unwrap().iter_mut().find(|c| Arc::ptr_eq(&c.stream, stream)) {
+        client.presence = presence;
+        client.status = status;
+    }
+}
+
+/// オペレーター権限を持っているか
+pub fn is_operator(clients: &SharedClients, stream: &Arc<Mutex<TcpStream>>) -> bool {
+    clients
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|c| Arc::ptr_eq(&c.stream, stream) && c.is_operator)
+}
+
+/// ニックネームの同一判定（大文字小文字を区別しない）
+pub fn is_same_name(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn connect(listener: &TcpListener) -> Arc<Mutex<TcpStream>> {
+        Arc::new(Mutex::new(TcpStream::connect(listener.local_addr().unwrap()).unwrap()))
+    }
+
+    #[test]
+    fn nicknames_are_unique_ignoring_case() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let clients = create_clients_list();
+        let (alice, bob) = (connect(&listener), connect(&listener));
+
+        assert!(add_client(&clients, alice.clone(), "Alice".to_string()));
+        assert!(!add_client(&clients, bob.clone(), "alice".to_string()));
+        assert!(add_client(&clients, bob.clone(), "bob".to_string()));
+        assert!(rename_client(&clients, &bob, "ALICE").is_err());
+        // 自分自身の大文字小文字だけを変える変更は許可する
+        assert_eq!(rename_client(&clients, &alice, "alice"), Ok("Alice".to_string()));
+    }
+}
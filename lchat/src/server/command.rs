@@ -0,0 +1,144 @@
+use std::{
+    env,
+    io::Result,
+    net::{Shutdown, TcpStream},
+    sync::{Arc, Mutex},
+};
+
+use crate::{Packet, DEFAULT_ROOM};
+use crate::server::{
+    client_manager::{self, SharedClients},
+    history::{self, SharedHistory},
+    network,
+};
+
+/// オペレーター権限のパスワードを指定する環境変数
+pub const OPER_PASSWORD_ENV: &str = "LCHAT_OPER_PASSWORD";
+
+/// コマンドの実行に必要な権限
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Permission {
+    User,
+    Operator,
+}
+
+/// コマンド実行時のコンテキスト
+pub struct CommandContext<'a> {
+    pub clients: &'a SharedClients,
+    pub history: &'a SharedHistory,
+    pub stream: &'a Arc<Mutex<TcpStream>>,
+    pub nickname: &'a str,
+    /// コマンドを送信したクライアントが表示中のルーム
+    pub room: &'a str,
+}
+
+impl CommandContext<'_> {
+    /// 実行したクライアントにのみ応答を返す
+    pub fn reply(&self, content: String) -> Result<()> {
+        network::send_packet(&Packet::command_response(content), self.stream)
+    }
+
+    /// 実行したクライアントにエラーを返す
+    pub fn error(&self, content: String) -> Result<()> {
+        network::send_packet(&Packet::error(content), self.stream)
+    }
+}
+
+/// サーバー側コマンドの定義
+pub struct Command {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub help: &'static str,
+    pub permission: Permission,
+    /// 必要な引数の最小個数
+    pub min_args: usize,
+    /// 前後の空白を除いた引数文字列を受け取って実行する
+    handler: fn(ctx: &CommandContext, args: &str) -> Result<()>,
+}
+
+/// 登録済みのコマンド一覧
+pub const COMMANDS: &[Command] = &[
+    Command { name: "help", usage: "/help", help: "Show server commands", permission: Permission::User, min_args: 0, handler: help },
+    Command { name: "who", usage: "/who [room]", help: "List users in a room", permission: Permission::User, min_args: 0, handler: who },
+    Command { name: "oper", usage: "/oper <password>", help: "Become a server operator", permission: Permission::User, min_args: 1, handler: oper },
+    Command { name: "kick", usage: "/kick <nick> [reason]", help: "Disconnect a user", permission: Permission::Operator, min_args: 1, handler: kick },
+];
+
+/// コマンドを解釈して実行（先頭の`/`は省略可）
+pub fn execute(ctx: &CommandContext, input: &str) -> Result<()> {
+    let input = input.trim().trim_start_matches('/');
+    let (name, args) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+    let args = args.trim();
+
+    let Some(command) = COMMANDS.iter().find(|c| c.name.eq_ignore_ascii_case(name)) else {
+        return ctx.error(format!("Unknown command: /{}", name));
+    };
+
+    if command.permission == Permission::Operator && !client_manager::is_operator(ctx.clients, ctx.stream) {
+        return ctx.error(format!("/{} requires operator privileges", command.name));
+    }
+    if args.split_whitespace().count() < command.min_args {
+        return ctx.error(format!("Usage: {}", command.usage));
+    }
+
+    (command.handler)(ctx, args)
+}
+
+fn help(ctx: &CommandContext, _args: &str) -> Result<()> {
+    let is_operator = client_manager::is_operator(ctx.clients, ctx.stream);
+    let lines: Vec<String> = COMMANDS
+        .iter()
+        .filter(|c| c.permission == Permission::User || is_operator)
+        .map(|c| format!("  {:<24} {}", c.usage, c.help))
+        .collect();
+    ctx.reply(format!("Server commands:\n{}", lines.join("\n")))
+}
+
+fn who(ctx: &CommandContext, args: &str) -> Result<()> {
+    let room = match args.split_whitespace().next() {
+        Some(room) => room.trim_start_matches('#').to_ascii_lowercase(),
+        None if ctx.room.is_empty() => DEFAULT_ROOM.to_string(),
+        None => ctx.room.to_string(),
+    };
+    let members = client_manager::get_room_members(ctx.clients, &room);
+    if members.is_empty() {
+        return ctx.error(format!("No users in #{}", room));
+    }
+    ctx.reply(format!("Users in #{} ({}): {}", room, members.len(), members.join(", ")))
+}
+
+fn oper(ctx: &CommandContext, args: &str) -> Result<()> {
+    let accepted = env::var(OPER_PASSWORD_ENV).is_ok_and(|password| !password.is_empty() && password == args);
+    if !accepted {
+        eprintln!("[Command] {} failed to become operator", ctx.nickname);
+        return ctx.error("Invalid operator password".to_string());
+    }
+    client_manager::set_operator(ctx.clients, ctx.stream);
+    println!("[Command] {} is now an operator", ctx.nickname);
+    ctx.reply("You are now a server operator".to_string())
+}
+
+fn kick(ctx: &CommandContext, args: &str) -> Result<()> {
+    let (target, reason) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    let Some((target_stream, target)) = client_manager::find_client_by_name(ctx.clients, target) else {
+        return ctx.error(format!("{} is not connected", target));
+    };
+
+    let reason = if reason.trim().is_empty() { "no reason given" } else { reason.trim() };
+    let notice = Packet::error(format!("You were kicked by {} ({})", ctx.nickname, reason));
+    // 対象の接続が切れかけていても実行したオペレーターの接続には影響させない
+    if let Err(e) = network::send_packet(&notice, &target_stream) {
+        eprintln!("[Command] Failed to notify {} of kick : {}", target, e);
+    }
+    // 参加中のルームにはサーバーからのお知らせとして通知する
+    for room in client_manager::get_rooms(ctx.clients, &target_stream) {
+        let announcement = Packet::system(format!("{} was kicked by {} ({})", target, ctx.nickname, reason), room.clone()).stamped();
+        history::publish(ctx.history, announcement, |packet| network::broadcast_to_room(ctx.clients, &room, packet))?;
+    }
+    // 切断すると対象クライアントの受信ループが終了し、通常の退出処理が行われる
+    if let Err(e) = target_stream.lock().unwrap().shutdown(Shutdown::Both) {
+        eprintln!("[Command] Failed to disconnect {} : {}", target, e);
+    }
+    println!("[Command] {} kicked {} ({})", ctx.nickname, target, reason);
+    ctx.reply(format!("Kicked {}", target))
+}
@@ -0,0 +1,415 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Result, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{current_timestamp, Packet, PacketType, ReactionInfo};
+
+/// 履歴ファイルの既定パス
+pub const HISTORY_FILE: &str = "lchat_history.jsonl";
+
+/// 入室時に再送する既定の件数
+pub const DEFAULT_REPLAY_COUNT: usize = 20;
+
+/// 履歴ファイルの1行分
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// 記録時刻（UNIX時間・秒）
+    pub timestamp: u64,
+    pub packet: Packet,
+}
+
+/// 絵文字ごとのリアクションしたユーザー
+///
+/// 名前を変えても取り消せるようセッションIDで区別し、表示名は集計の送信にのみ使う
+/// 履歴ファイルから読み込んだリアクションはセッションが分からないためNone
+struct Reaction {
+    emoji: String,
+    users: Vec<(Option<u64>, String)>,
+}
+
+/// メッセージ履歴
+pub struct History {
+    file: File,
+    /// 履歴ファイルに記録しないパケットにも付与したIDの上限を保存するファイル
+    id_file: PathBuf,
+    /// ルームごとの直近のメッセージ（最大replay_count件。入退室は含めない）
+    recent: HashMap<String, VecDeque<HistoryEntry>>,
+    replay_count: usize,
+    /// 次に付与するパケットID
+    next_id: u64,
+    /// ルームごとの最後に付与した通し番号
+    sequences: HashMap<String, u64>,
+    /// メッセージIDごとの送信者のセッションIDとルーム（編集・削除の権限確認に使う）
+    ///
+    /// 履歴ファイルから読み込んだメッセージは送信者の接続が残っていないためNone（オペレーターのみ編集できる）
+    owners: HashMap<u64, (Option<u64>, String)>,
+    /// メッセージIDごとのリアクションの集計
+    reactions: HashMap<u64, Vec<Reaction>>,
+}
+
+/// 共有メッセージ履歴
+pub type SharedHistory = Arc<Mutex<History>>;
+
+/// 履歴ファイルを開き、既存の履歴を読み込む（ファイルがなければ作成）
+pub fn open_history(path: impl AsRef<Path>, replay_count: usize) -> Result<SharedHistory> {
+    let path = path.as_ref();
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut id_file = path.as_os_str().to_owned();
+    id_file.push(".next_id");
+    let mut history = History {
+        file,
+        id_file: PathBuf::from(id_file),
+        recent: HashMap::new(),
+        replay_count,
+        next_id: 1,
+        sequences: HashMap::new(),
+        owners: HashMap::new(),
+        reactions: HashMap::new(),
+    };
+
+    for line in BufReader::new(File::open(path)?).lines() {
+        // 壊れた行は読み飛ばす
+        let Ok(mut entry) = serde_json::from_str::<HistoryEntry>(&line?) else {
+            continue;
+        };
+        // 時刻を持たない古い形式のパケットには記録時刻を補う
+        entry.packet.timestamp.get_or_insert(entry.timestamp);
+        // 再起動後もIDと通し番号が重複しないよう続きから付与する
+        if let Some(id) = entry.packet.id {
+            history.next_id = history.next_id.max(id + 1);
+        }
+        if let (Some(room), Some(seq)) = (entry.packet.room.clone(), entry.packet.seq) {
+            let last = history.sequences.entry(room).or_default();
+            *last = (*last).max(seq);
+        }
+        if let (Some(message_id), Some(reactions)) = (entry.packet.message_id, &entry.packet.reactions) {
+            let reactions = reactions
+                .iter()
+                .map(|r| Reaction {
+                    emoji: r.emoji.clone(),
+                    users: r.users.iter().map(|u| (None, u.clone())).collect(),
+                })
+                .collect();
+            history.reactions.insert(message_id, reactions);
+        }
+        history.remember(entry);
+    }
+    // 個人宛てのメッセージなど履歴ファイルに残らないパケットのIDも再利用しない
+    if let Some(next_id) = fs::read_to_string(&history.id_file).ok().and_then(|id| id.trim().parse().ok()) {
+        history.next_id = history.next_id.max(next_id);
+    }
+
+    Ok(Arc::new(Mutex::new(history)))
+}
+
+/// パケットにIDと通し番号を付与して送信し、履歴に記録する
+///
+/// 付与から送信までを排他的に行うため、クライアントには通し番号順に届く
+pub fn publish(
+    history: &SharedHistory,
+    packet: Packet,
+    send: impl FnOnce(&Packet) -> Result<()>,
+) -> Result<Packet> {
+    let mut history = history.lock().unwrap();
+    let packet = history.assign(packet, None);
+    send(&packet)?;
+    history.record(&packet);
+    Ok(packet)
+}
+
+/// `publish`と同様に送信・記録し、送信者のセッションをメッセージの所有者・リアクションしたユーザーとして登録する
+pub fn publish_from(
+    history: &SharedHistory,
+    packet: Packet,
+    session: u64,
+    send: impl FnOnce(&Packet) -> Result<()>,
+) -> Result<Packet> {
+    let mut history = history.lock().unwrap();
+    let packet = history.assign(packet, Some(session));
+    send(&packet)?;
+    history.record(&packet);
+    if let Some(owner) = packet.id.and_then(|id| history.owners.get_mut(&id)) {
+        owner.0 = Some(session);
+    }
+    Ok(packet)
+}
+
+/// `publish`と同様に送信・記録し、送信時にはパケットのルームの直近の履歴（このパケットを含まない）も渡す
+///
+/// 履歴の取得から送信までを同じ排他区間で行うため、入室時に再送する履歴と他のメッセージが重複・前後しない
+pub fn publish_with_recent(
+    history: &SharedHistory,
+    packet: Packet,
+    send: impl FnOnce(&Packet, Vec<Packet>) -> Result<()>,
+) -> Result<Packet> {
+    let mut history = history.lock().unwrap();
+    let recent = packet.room.as_deref().map(|room| history.recent(room)).unwrap_or_default();
+    let packet = history.assign(packet, None);
+    send(&packet, recent)?;
+    history.record(&packet);
+    Ok(packet)
+}
+
+/// メッセージの送信者のセッションIDとルームを取得（存在しない・削除済みならNone）
+pub fn message_owner(history: &SharedHistory, message_id: u64) -> Option<(Option<u64>, String)> {
+    history.lock().unwrap().owners.get(&message_id).cloned()
+}
+
+impl History {
+    /// パケットにIDと通し番号を付与する（リアクションには反映後の集計も付ける）
+    fn assign(&mut self, packet: Packet, session: Option<u64>) -> Packet {
+        let id = self.next_id;
+        self.next_id += 1;
+        // ルーム宛てのパケットのみ通し番号を付与する
+        let seq = packet.room.clone().map(|room| {
+            let seq = self.sequences.entry(room).or_default();
+            *seq += 1;
+            *seq
+        });
+        let mut packet = Packet { id: Some(id), seq, ..packet };
+        if packet.packet_type == PacketType::Reaction {
+            packet.reactions = Some(self.toggle_reaction(&packet, session));
+        }
+        packet
+    }
+
+    /// ルームの直近の履歴を古い順に取得
+    fn recent(&self, room: &str) -> Vec<Packet> {
+        self.recent
+            .get(room)
+            .map(|entries| entries.iter().map(|e| e.packet.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// 中継したパケットをファイルに追記して反映（Message・Edit・Delete・Reaction・Join・Leaveのみ。それ以外はIDの上限のみ保存）
+    fn record(&mut self, packet: &Packet) {
+        let is_recorded = matches!(
+            packet.packet_type,
+            PacketType::Message
+                | PacketType::Edit
+                | PacketType::Delete
+                | PacketType::Reaction
+                | PacketType::Join
+                | PacketType::Leave
+        );
+        if !is_recorded || packet.room.is_none() {
+            // ファイルからIDを復元できないため、付与済みのIDの上限を別に保存する
+            if let Err(e) = fs::write(&self.id_file, self.next_id.to_string()) {
+                eprintln!("[history] Failed to save next id : {}", e);
+            }
+            return;
+        }
+
+        // 中継時に付与した時刻があればそれを使う
+        let timestamp = packet.timestamp.unwrap_or_else(current_timestamp);
+        let entry = HistoryEntry { timestamp, packet: packet.clone() };
+
+        match serde_json::to_string(&entry) {
+            Ok(json) => {
+                if let Err(e) = writeln!(self.file, "{}", json) {
+                    eprintln!("[history] Failed to write history : {}", e);
+                }
+            },
+            Err(e) => eprintln!("[history] Failed to serialize history : {}", e),
+        }
+        self.remember(entry);
+    }
+
+    /// リアクションの追加・取り消しを反映して集計を作成（同じセッションの同じ絵文字は取り消し）
+    fn toggle_reaction(&mut self, packet: &Packet, session: Option<u64>) -> Vec<ReactionInfo> {
+        let Some(message_id) = packet.message_id else {
+            return Vec::new();
+        };
+        let reactions = self.reactions.entry(message_id).or_default();
+        let nickname = packet.nickname.clone().unwrap_or_default();
+        let reacted = |users: &[(Option<u64>, String)]| session.is_some() && users.iter().any(|(s, _)| *s == session);
+
+        match reactions.iter_mut().find(|r| r.emoji == packet.content) {
+            Some(reaction) if reacted(&reaction.users) => reaction.users.retain(|(s, _)| *s != session),
+            Some(reaction) => reaction.users.push((session, nickname)),
+            None => reactions.push(Reaction { emoji: packet.content.clone(), users: vec![(session, nickname)] }),
+        }
+        reactions.retain(|r| !r.users.is_empty());
+        reactions
+            .iter()
+            .map(|r| ReactionInfo {
+                emoji: r.emoji.clone(),
+                users: r.users.iter().map(|(_, nickname)| nickname.clone()).collect(),
+            })
+            .collect()
+    }
+
+    /// 記録したパケットを直近の履歴と送信者の一覧に反映
+    ///
+    /// 編集・削除・リアクションは対象のメッセージに反映し、再送時には反映後の内容を送る
+    fn remember(&mut self, entry: HistoryEntry) {
+        let Some(room) = entry.packet.room.clone() else {
+            return;
+        };
+
+        match entry.packet.packet_type {
+            PacketType::Reaction => {
+                let (Some(message_id), Some(reactions)) = (entry.packet.message_id, entry.packet.reactions) else {
+                    return;
+                };
+                let target = self
+                    .recent
+                    .get_mut(&room)
+                    .and_then(|entries| entries.iter_mut().find(|e| e.packet.id == Some(message_id)));
+                if let Some(target) = target {
+                    target.packet.reactions = Some(reactions).filter(|r| !r.is_empty());
+                }
+            },
+            PacketType::Edit | PacketType::Delete => {
+                let Some(message_id) = entry.packet.message_id else {
+                    return;
+                };
+                if entry.packet.packet_type == PacketType::Delete {
+                    self.owners.remove(&message_id);
+                    self.reactions.remove(&message_id);
+                }
+                let target = self
+                    .recent
+                    .get_mut(&room)
+                    .and_then(|entries| entries.iter_mut().find(|e| e.packet.id == Some(message_id)));
+                // 送信者・時刻・IDは元のメッセージのものを残す
+                if let Some(target) = target {
+                    target.packet.packet_type = entry.packet.packet_type;
+                    target.packet.content = entry.packet.content;
+                }
+            },
+            PacketType::Message => {
+                if let Some(id) = entry.packet.id {
+                    self.owners.insert(id, (None, room.clone()));
+                }
+                let entries = self.recent.entry(room).or_default();
+                entries.push_back(entry);
+                while entries.len() > self.replay_count {
+                    entries.pop_front();
+                }
+            },
+            // 入退室はファイルにのみ残し、再送する件数に数えない
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// テストごとの一時的な履歴ファイル（終了時に削除する）
+    struct TempHistory(PathBuf);
+
+    impl TempHistory {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("lchat_test_{}_{}.jsonl", name, std::process::id()));
+            let temp = Self(path);
+            temp.remove();
+            temp
+        }
+
+        fn open(&self) -> SharedHistory {
+            open_history(&self.0, 3).unwrap()
+        }
+
+        fn remove(&self) {
+            let mut id_file = self.0.as_os_str().to_owned();
+            id_file.push(".next_id");
+            let _ = fs::remove_file(&self.0);
+            let _ = fs::remove_file(id_file);
+        }
+    }
+
+    impl Drop for TempHistory {
+        fn drop(&mut self) {
+            self.remove();
+        }
+    }
+
+    fn message(content: &str) -> Packet {
+        Packet::message(content.to_string(), "alice".to_string(), "lobby".to_string())
+    }
+
+    fn reaction(history: &SharedHistory, message_id: u64, nickname: &str, session: u64) -> Vec<ReactionInfo> {
+        let packet = Packet::reaction(message_id, "👍".to_string(), nickname.to_string(), "lobby".to_string());
+        publish_from(history, packet, session, |_| Ok(())).unwrap().reactions.unwrap()
+    }
+
+    #[test]
+    fn ids_and_sequences_continue_after_reopen() {
+        let temp = TempHistory::new("ids");
+        let history = temp.open();
+        publish(&history, message("hello"), |_| Ok(())).unwrap();
+        // 履歴ファイルに残らない個人宛てのメッセージのIDも再利用しない
+        let private = Packet::private_message("psst".to_string(), "alice".to_string(), "bob".to_string());
+        publish(&history, private, |_| Ok(())).unwrap();
+        drop(history);
+
+        let packet = publish(&temp.open(), message("again"), |_| Ok(())).unwrap();
+        assert_eq!(packet.id, Some(3));
+        assert_eq!(packet.seq, Some(2));
+    }
+
+    #[test]
+    fn edits_and_deletes_rewrite_replayed_messages() {
+        let temp = TempHistory::new("edits");
+        let history = temp.open();
+        let first = publish(&history, message("typo"), |_| Ok(())).unwrap().id.unwrap();
+        let second = publish(&history, message("oops"), |_| Ok(())).unwrap().id.unwrap();
+        let edit = Packet::edit(first, "fixed".to_string(), "alice".to_string(), "lobby".to_string());
+        publish(&history, edit, |_| Ok(())).unwrap();
+        publish(&history, Packet::delete(second, "alice".to_string(), "lobby".to_string()), |_| Ok(())).unwrap();
+        drop(history);
+
+        let recent = temp.open().lock().unwrap().recent("lobby");
+        assert_eq!(recent.len(), 2);
+        assert_eq!((&recent[0].packet_type, recent[0].content.as_str()), (&PacketType::Edit, "fixed"));
+        assert_eq!(recent[1].packet_type, PacketType::Delete);
+    }
+
+    #[test]
+    fn replay_window_counts_only_messages() {
+        let temp = TempHistory::new("window");
+        let history = temp.open();
+        for content in ["one", "two", "three", "four"] {
+            publish(&history, message(content), |_| Ok(())).unwrap();
+        }
+        publish(&history, Packet::join("bob".to_string(), "lobby".to_string()), |_| Ok(())).unwrap();
+
+        let recent = history.lock().unwrap().recent("lobby");
+        let contents: Vec<_> = recent.iter().map(|p| p.content.as_str()).collect();
+        assert_eq!(contents, ["two", "three", "four"]);
+    }
+
+    #[test]
+    fn loaded_messages_have_no_owner_session() {
+        let temp = TempHistory::new("owners");
+        let history = temp.open();
+        let id = publish_from(&history, message("mine"), 7, |_| Ok(())).unwrap().id.unwrap();
+        assert_eq!(message_owner(&history, id), Some((Some(7), "lobby".to_string())));
+        drop(history);
+
+        assert_eq!(message_owner(&temp.open(), id), Some((None, "lobby".to_string())));
+    }
+
+    #[test]
+    fn reactions_toggle_per_session() {
+        let temp = TempHistory::new("reactions");
+        let history = temp.open();
+        let id = publish(&history, message("lunch?"), |_| Ok(())).unwrap().id.unwrap();
+
+        assert_eq!(reaction(&history, id, "bob", 1)[0].users, ["bob"]);
+        assert_eq!(reaction(&history, id, "carol", 2)[0].users, ["bob", "carol"]);
+        // 名前を変えても同じセッションなら取り消しになる
+        assert_eq!(reaction(&history, id, "robert", 1)[0].users, ["carol"]);
+        assert!(reaction(&history, id, "carol", 2).is_empty());
+        assert_eq!(history.lock().unwrap().recent("lobby")[0].reactions, None);
+    }
+}
@@ -4,17 +4,22 @@ use std::{
     sync::{Arc, Mutex},
 };
 
-use crate::{Packet, PacketType};
+use crate::{normalize_reaction, sanitize_content, validate_nickname, MessageKind, Packet, PacketType, DEFAULT_ROOM, MAX_STATUS_LEN};
 use crate::server::{
-    client_manager::SharedClients,
+    client_manager::{self, SharedClients},
+    command::{self, CommandContext},
+    history::{self, SharedHistory},
     network,
 };
 
+/// ルーム名の最大長
+const MAX_ROOM_NAME_LEN: usize = 32;
+
 /// メッセージ受信とパケット処理のメインループ
 pub fn handle_client_messages(
     stream: &Arc<Mutex<TcpStream>>,
     clients: &SharedClients,
-    nickname: &str,
+    history: &SharedHistory,
 ) -> Result<()> {
     let reader = {
         let guard = stream.lock().unwrap();
@@ -30,11 +35,30 @@ pub fn handle_client_messages(
         let trimmed = msg.trim();
         
         if let Ok(packet) = Packet::from_json(trimmed) {
-            packet.log();
+            // ニックネームは変更され得るため、パケットごとに現在の名前を取得する
+            let Some(nickname) = client_manager::get_client_name(clients, stream) else {
+                break;
+            };
+            let nickname = nickname.as_str();
+            let session = client_manager::get_session(clients, stream).unwrap_or_default();
+            let packet = authenticate(packet, nickname);
+            // 入力中の通知は頻繁に届くためログに残さない
+            if packet.packet_type != PacketType::Typing {
+                packet.log();
+            }
 
             match packet.packet_type {
-                PacketType::Message => handle_message(clients, &packet, nickname),
+                PacketType::NicknameChange => handle_nickname_change(clients, history, &packet, stream),
+                PacketType::Message => handle_message(clients, history, &packet, stream, nickname, session),
+                PacketType::PrivateMessage => handle_private_message(clients, history, &packet, stream, nickname),
+                PacketType::Edit | PacketType::Delete => handle_edit(clients, history, &packet, stream, nickname, session),
+                PacketType::Reaction => handle_reaction(clients, history, &packet, stream, nickname, session),
+                PacketType::Typing => handle_typing(clients, &packet, stream, nickname),
+                PacketType::Presence => handle_presence(clients, history, &packet, stream, nickname),
+                PacketType::Join => handle_join(clients, history, &packet, stream, nickname),
+                PacketType::Leave => handle_leave(clients, history, &packet, stream, nickname),
                 PacketType::InfoRequest => handle_info_request(clients, &packet, stream),
+                PacketType::Command => handle_command(clients, history, &packet, stream, nickname),
                 _ => Ok(()),
             }?;
         }
@@ -43,6 +67,38 @@ pub fn handle_client_messages(
     Ok(())
 }
 
+/// 送信者名をサーバーが認証したニックネームで上書きし、受信時刻を付与する
+///
+/// クライアントが申告した名前が異なる場合は両方をログに残す
+fn authenticate(packet: Packet, nickname: &str) -> Packet {
+    let mut packet = packet.stamped();
+    if let Some(claimed) = packet.nickname.as_deref().filter(|claimed| *claimed != nickname) {
+        eprintln!("[Auth] claimed nickname '{}' does not match authenticated '{}'", claimed, nickname);
+    }
+    packet.nickname = Some(nickname.to_string());
+    packet
+}
+
+fn handle_nickname_change(
+    clients: &SharedClients,
+    history: &SharedHistory,
+    packet: &Packet,
+    stream: &Arc<Mutex<TcpStream>>
+) -> Result<()> {
+    let new_name = packet.content.trim();
+    let result = validate_nickname(new_name)
+        .and_then(|_| client_manager::rename_client(clients, stream, new_name));
+
+    match result {
+        Ok(old_name) => {
+            let change_packet = Packet::nickname_change(old_name, new_name.to_string()).stamped();
+            history::publish(history, change_packet, |packet| network::broadcast(clients, packet))?;
+            network::broadcast_user_list(clients)
+        },
+        Err(reason) => network::send_packet(&Packet::error(reason), stream),
+    }
+}
+
 fn handle_info_request(
     clients: &SharedClients,
     packet: &Packet,
@@ -50,21 +106,302 @@ fn handle_info_request(
 ) -> Result<()> {
     match packet.content.as_str() {
         "connection" => {
-            let count = clients.lock().unwrap().len();
+            let count = client_manager::get_client_count(clients);
             let connection_packet = Packet::connection(count);
             network::send_packet(&connection_packet, stream)?;
         },
+        "users" => {
+            let user_list_packet = Packet::user_list(client_manager::get_user_list(clients));
+            network::send_packet(&user_list_packet, stream)?;
+        },
         _ => {},
     }
     Ok(())
 }
 
+fn handle_command(
+    clients: &SharedClients,
+    history: &SharedHistory,
+    packet: &Packet,
+    stream: &Arc<Mutex<TcpStream>>,
+    nickname: &str
+) -> Result<()> {
+    let ctx = CommandContext {
+        clients,
+        history,
+        stream,
+        nickname,
+        room: packet.room.as_deref().unwrap_or_default(),
+    };
+    command::execute(&ctx, &packet.content)
+}
+
 fn handle_message(
     clients: &SharedClients,
+    history: &SharedHistory,
+    packet: &Packet,
+    stream: &Arc<Mutex<TcpStream>>,
+    nickname: &str,
+    session: u64
+) -> Result<()> {
+    let room = packet.room.as_deref().unwrap_or(DEFAULT_ROOM);
+    if !client_manager::is_in_room(clients, stream, room) {
+        let error_packet = Packet::error(format!("You are not in #{}", room));
+        return network::send_packet(&error_packet, stream);
+    }
+
+    // 返信先は同じルームの削除されていないメッセージに限る
+    if let Some(reply_to) = packet.reply_to {
+        let parent_room = history::message_owner(history, reply_to).map(|(_, room)| room);
+        if parent_room.as_deref() != Some(room) {
+            let error_packet = Packet::error(format!("Message {} not found in #{}", reply_to, room));
+            return network::send_packet(&error_packet, stream);
+        }
+    }
+
+    // サーバーからのお知らせを装えないようにする
+    if packet.kind == Some(MessageKind::System) {
+        let error_packet = Packet::error("System messages can only be sent by the server".to_string());
+        return network::send_packet(&error_packet, stream);
+    }
+
+    let content = sanitize_content(&packet.content);
+    if content.is_empty() {
+        return Ok(());
+    }
+
+    let message_packet = Packet {
+        reply_to: packet.reply_to,
+        kind: packet.kind,
+        ..Packet::message(content, nickname.to_string(), room.to_string())
+    }.stamped();
+    history::publish_from(history, message_packet, session, |packet| network::broadcast_to_room(clients, room, packet))?;
+    Ok(())
+}
+
+fn handle_private_message(
+    clients: &SharedClients,
+    history: &SharedHistory,
     packet: &Packet,
+    stream: &Arc<Mutex<TcpStream>>,
     nickname: &str
 ) -> Result<()> {
-    let message_packet = Packet::message(packet.content.clone(), nickname.to_string());
-    network::broadcast(clients, &message_packet)?;
+    let target = packet.target.as_deref().unwrap_or_default();
+    let Some((target_stream, target)) = client_manager::find_client_by_name(clients, target) else {
+        let error_packet = Packet::error(format!("{} is not connected", target));
+        return network::send_packet(&error_packet, stream);
+    };
+
+    let private_packet = Packet::private_message(sanitize_content(&packet.content), nickname.to_string(), target.clone()).stamped();
+    history::publish(history, private_packet, |packet| {
+        // 宛先の接続の失敗で送信者の接続を切らないよう、送信者にはエラーとして返す
+        if let Err(e) = network::send_packet(packet, &target_stream) {
+            eprintln!("[message_handler] Failed to send to client {} : {}", target, e);
+            let error_packet = Packet::error(format!("Failed to deliver message to {}", target));
+            return network::send_packet(&error_packet, stream);
+        }
+        // 自分宛てでなければ送信者にも返す
+        if !Arc::ptr_eq(&target_stream, stream) {
+            network::send_packet(packet, stream)?;
+        }
+        Ok(())
+    })?;
     Ok(())
-}
\ No newline at end of file
+}
+
+/// メッセージの編集・削除（送信した接続かオペレーターのみ）
+///
+/// ニックネームは変更・再利用され得るため、送信者は接続ごとのセッションIDで判定する
+fn handle_edit(
+    clients: &SharedClients,
+    history: &SharedHistory,
+    packet: &Packet,
+    stream: &Arc<Mutex<TcpStream>>,
+    nickname: &str,
+    session: u64
+) -> Result<()> {
+    let message_id = packet.message_id.unwrap_or_default();
+    let Some((owner, room)) = history::message_owner(history, message_id) else {
+        let error_packet = Packet::error(format!("Message {} not found", message_id));
+        return network::send_packet(&error_packet, stream);
+    };
+    if owner != Some(session) && !client_manager::is_operator(clients, stream) {
+        let error_packet = Packet::error("You can only edit or delete your own messages".to_string());
+        return network::send_packet(&error_packet, stream);
+    }
+
+    let edit_packet = if packet.packet_type == PacketType::Edit {
+        let content = sanitize_content(&packet.content);
+        if content.is_empty() {
+            let error_packet = Packet::error("Message is empty (use /delete to remove it)".to_string());
+            return network::send_packet(&error_packet, stream);
+        }
+        Packet::edit(message_id, content, nickname.to_string(), room.clone())
+    } else {
+        Packet::delete(message_id, nickname.to_string(), room.clone())
+    };
+    history::publish(history, edit_packet.stamped(), |packet| network::broadcast_to_room(clients, &room, packet))?;
+    Ok(())
+}
+
+/// メッセージへのリアクション（同じ絵文字をもう一度送ると取り消す）
+fn handle_reaction(
+    clients: &SharedClients,
+    history: &SharedHistory,
+    packet: &Packet,
+    stream: &Arc<Mutex<TcpStream>>,
+    nickname: &str,
+    session: u64
+) -> Result<()> {
+    let message_id = packet.message_id.unwrap_or_default();
+    let room = history::message_owner(history, message_id)
+        .map(|(_, room)| room)
+        .filter(|room| client_manager::is_in_room(clients, stream, room));
+    let Some(room) = room else {
+        let error_packet = Packet::error(format!("Message {} not found", message_id));
+        return network::send_packet(&error_packet, stream);
+    };
+    let emoji = match normalize_reaction(&packet.content) {
+        Ok(emoji) => emoji,
+        Err(reason) => return network::send_packet(&Packet::error(reason), stream),
+    };
+
+    let reaction_packet = Packet::reaction(message_id, emoji, nickname.to_string(), room.clone()).stamped();
+    history::publish_from(history, reaction_packet, session, |packet| network::broadcast_to_room(clients, &room, packet))?;
+    Ok(())
+}
+
+/// 入力中の通知を同じルームの他の参加者へ転送（記録はしない）
+fn handle_typing(
+    clients: &SharedClients,
+    packet: &Packet,
+    stream: &Arc<Mutex<TcpStream>>,
+    nickname: &str
+) -> Result<()> {
+    let room = packet.room.as_deref().unwrap_or(DEFAULT_ROOM);
+    if !client_manager::is_in_room(clients, stream, room) {
+        return Ok(());
+    }
+    let typing_packet = Packet::typing(nickname.to_string(), room.to_string());
+    network::broadcast_to_room_except(clients, room, &typing_packet, stream)
+}
+
+/// 在席状況を変更して全員に通知
+fn handle_presence(
+    clients: &SharedClients,
+    history: &SharedHistory,
+    packet: &Packet,
+    stream: &Arc<Mutex<TcpStream>>,
+    nickname: &str
+) -> Result<()> {
+    let presence = packet.presence.unwrap_or_default();
+    // 説明は1行にまとめて切り詰める
+    let status: String = sanitize_content(&packet.content)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(MAX_STATUS_LEN)
+        .collect();
+
+    client_manager::set_presence(clients, stream, presence, Some(status.clone()).filter(|s| !s.is_empty()));
+    let presence_packet = Packet::presence_change(nickname.to_string(), presence, status).stamped();
+    history::publish(history, presence_packet, |packet| network::broadcast(clients, packet))?;
+    Ok(())
+}
+
+fn handle_join(
+    clients: &SharedClients,
+    history: &SharedHistory,
+    packet: &Packet,
+    stream: &Arc<Mutex<TcpStream>>,
+    nickname: &str
+) -> Result<()> {
+    let room = match packet.room.as_deref().and_then(normalize_room_name) {
+        Some(room) => room,
+        None => {
+            let error_packet = Packet::error(format!(
+                "Invalid room name (1-{} characters of a-z, 0-9, '-' or '_')",
+                MAX_ROOM_NAME_LEN
+            ));
+            return network::send_packet(&error_packet, stream);
+        }
+    };
+
+    let join_packet = Packet::join(nickname.to_string(), room.clone()).stamped();
+    if client_manager::is_in_room(clients, stream, &room) {
+        // 参加済みの場合は本人にのみ通知して表示ルームを切り替えさせる
+        return network::send_packet(&join_packet, stream);
+    }
+    join_and_replay(clients, history, stream, join_packet, &room)
+}
+
+fn handle_leave(
+    clients: &SharedClients,
+    history: &SharedHistory,
+    packet: &Packet,
+    stream: &Arc<Mutex<TcpStream>>,
+    nickname: &str
+) -> Result<()> {
+    let room = packet.room.as_deref().and_then(normalize_room_name).unwrap_or_default();
+    if !client_manager::leave_room(clients, stream, &room) {
+        let error_packet = Packet::error(format!("You are not in #{}", room));
+        return network::send_packet(&error_packet, stream);
+    }
+
+    // 退出した本人にも通知する
+    let leave_packet = Packet::leave(nickname.to_string(), room.clone()).stamped();
+    history::publish(history, leave_packet, |packet| {
+        network::send_packet(packet, stream)?;
+        network::broadcast_to_room(clients, &room, packet)
+    })?;
+    Ok(())
+}
+
+/// ルームに入室して通知・記録し、入室したクライアントへ直前までの履歴を送信
+///
+/// 入室から履歴の送信までを履歴の排他区間で行い、その間のメッセージが履歴と重複したり先に届いたりしないようにする
+pub fn join_and_replay(
+    clients: &SharedClients,
+    history: &SharedHistory,
+    stream: &Arc<Mutex<TcpStream>>,
+    join_packet: Packet,
+    room: &str
+) -> Result<()> {
+    history::publish_with_recent(history, join_packet, |packet, recent| {
+        client_manager::join_room(clients, stream, room);
+        network::broadcast_to_room(clients, room, packet)?;
+        if !recent.is_empty() {
+            network::send_packet(&Packet::history(room.to_string(), recent), stream)?;
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// ルーム名を正規化（先頭の'#'を除き小文字化）、不正ならNone
+fn normalize_room_name(name: &str) -> Option<String> {
+    let name = name.trim().trim_start_matches('#').to_ascii_lowercase();
+    let valid = !name.is_empty()
+        && name.len() <= MAX_ROOM_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn room_name_is_normalized() {
+        assert_eq!(normalize_room_name(" #Dev-Team_1 ").as_deref(), Some("dev-team_1"));
+    }
+
+    #[test]
+    fn room_name_rejects_invalid() {
+        assert_eq!(normalize_room_name("#"), None);
+        assert_eq!(normalize_room_name("two words"), None);
+        assert_eq!(normalize_room_name("日本"), None);
+        assert_eq!(normalize_room_name(&"a".repeat(MAX_ROOM_NAME_LEN + 1)), None);
+    }
+}
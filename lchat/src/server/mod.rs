@@ -1,3 +1,5 @@
 pub mod client_manager;
+pub mod command;
+pub mod history;
 pub mod message_handler;
 pub mod network;
\ No newline at end of file
@@ -4,9 +4,9 @@ use std::{
     sync::{Arc, Mutex},
 };
 
-use crate::{Packet, PacketType};
+use crate::{validate_nickname, Packet, PacketType};
 use crate::server::{
-    client_manager::SharedClients,
+    client_manager::{self, SharedClients},
 };
 
 pub fn broadcast(clients: &SharedClients, packet: &Packet) -> Result<()> {
@@ -19,29 +19,82 @@ pub fn broadcast(clients: &SharedClients, packet: &Packet) -> Result<()> {
     Ok(())
 }
 
-/// クライアントからニックネームを取得
-pub fn get_nickname(stream: &Arc<Mutex<TcpStream>>) -> Result<String> {
-    let guard = stream.lock().unwrap();
-    let mut reader = BufReader::new(
-        guard
-            .try_clone()
-            .expect("failed to clone stream for nickname read"),
-    );
-    let mut buf = String::new();
-    reader.read_line(&mut buf)?;
-    
-    let trimmed = buf.trim();
-    let packet = Packet::from_json(trimmed)
-        .map_err(|_| Error::new(ErrorKind::InvalidInput, "invalid JSON packet"))?;
-    
-    if packet.packet_type == PacketType::NicknameResponse {
-        if packet.content.is_empty() {
-            Err(Error::new(ErrorKind::InvalidInput, "nickname is empty"))
-        } else {
-            Ok(packet.content)
+/// ルームの参加者にのみ送信
+pub fn broadcast_to_room(clients: &SharedClients, room: &str, packet: &Packet) -> Result<()> {
+    let list = clients.lock().unwrap();
+    for client in list.iter().filter(|c| c.rooms.contains(room)) {
+        if let Err(e) = send_packet(packet, &client.stream) {
+            eprintln!("[message_handler] Failed to send to client {} : {}", client.name, e);
+        }
+    }
+    Ok(())
+}
+
+/// ルームの参加者のうち`except`以外に送信
+pub fn broadcast_to_room_except(
+    clients: &SharedClients,
+    room: &str,
+    packet: &Packet,
+    except: &Arc<Mutex<TcpStream>>,
+) -> Result<()> {
+    let list = clients.lock().unwrap();
+    for client in list.iter().filter(|c| c.rooms.contains(room) && !Arc::ptr_eq(&c.stream, except)) {
+        if let Err(e) = send_packet(packet, &client.stream) {
+            eprintln!("[message_handler] Failed to send to client {} : {}", client.name, e);
+        }
+    }
+    Ok(())
+}
+
+/// 最新のユーザー一覧を全クライアントへ送信
+pub fn broadcast_user_list(clients: &SharedClients) -> Result<()> {
+    let packet = Packet::user_list(client_manager::get_user_list(clients));
+    broadcast(clients, &packet)
+}
+
+/// クライアントからニックネームを取得し、クライアントリストへ登録
+///
+/// 受理できないニックネームの場合はエラーとニックネーム要求を再送し、受理されるまで待つ
+pub fn get_nickname(stream: &Arc<Mutex<TcpStream>>, clients: &SharedClients) -> Result<String> {
+    let mut reader = {
+        let guard = stream.lock().unwrap();
+        BufReader::new(
+            guard
+                .try_clone()
+                .expect("failed to clone stream for nickname read"),
+        )
+    };
+
+    loop {
+        let mut buf = String::new();
+        if reader.read_line(&mut buf)? == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "disconnected before sending nickname"));
+        }
+
+        let trimmed = buf.trim();
+        let packet = Packet::from_json(trimmed)
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "invalid JSON packet"))?;
+
+        if packet.packet_type != PacketType::NicknameResponse {
+            return Err(Error::new(ErrorKind::InvalidInput, "invalid packet type for nickname"));
+        }
+
+        let nickname = packet.content.trim().to_string();
+        let result = validate_nickname(&nickname).and_then(|_| {
+            if client_manager::add_client(clients, Arc::clone(stream), nickname.clone()) {
+                Ok(())
+            } else {
+                Err(format!("Nickname '{}' is already in use", nickname))
+            }
+        });
+
+        match result {
+            Ok(()) => return Ok(nickname),
+            Err(reason) => {
+                send_packet(&Packet::error(reason), stream)?;
+                send_packet(&Packet::nickname_request(), stream)?;
+            },
         }
-    } else {
-        Err(Error::new(ErrorKind::InvalidInput, "invalid packet type for nickname"))
     }
 }
 